@@ -0,0 +1,111 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Renders a module to an audio file with the same settings and code path the bot uses for playback,
+// useful for checking how a module will sound without joining a voice channel.
+
+use std::{env, fs, path::Path, process::ExitCode, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use modulo::{encoder::AudioFormat, render::{render, RenderSettings}, session::Interpolation};
+
+const USAGE: &str = "Usage: modulo-render <input> <output> [options]
+
+The output format is picked by the extension (wav, flac or opus), WAV by default.
+
+Options:
+  --interpolation <default|none|linear|cubic|sinc8>
+  --amiga <none|auto|a500|a1200|unfiltered>
+  --stereo <0-200>       Stereo separation in percent
+  --tempo <factor>       Tempo multiplier, e.g. 1.5
+  --subsong <n>          Subsong to start from, counting from 0
+  --autosubsong          Play all subsongs one after another";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut paths = Vec::new();
+    let mut settings = RenderSettings::default();
+    let mut subsong = 0;
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            paths.push(arg);
+            continue;
+        }
+        if arg == "--autosubsong" {
+            settings.autosubsong = true;
+            continue;
+        }
+
+        let value = args.next().ok_or(anyhow!("Missing value for {arg}"))?;
+        match arg.as_str() {
+            "--interpolation" => {
+                settings.interpolation = Interpolation::from_name(&value)
+                    .ok_or(anyhow!("Unknown interpolation {value}"))?;
+            },
+            "--amiga" => {
+                if !["none", "auto", "a500", "a1200", "unfiltered"].contains(&value.as_str()) {
+                    return Err(anyhow!("Unknown Amiga resampler mode {value}"));
+                }
+                // Same as /amigaresampler
+                settings.amiga_enabled = value != "none";
+                settings.amiga_mode = value;
+            },
+            "--stereo" => {
+                settings.stereo_separation = value.parse().ok()
+                    .filter(|separation| (0..=200).contains(separation))
+                    .ok_or(anyhow!("Stereo separation must be between 0 and 200"))?;
+            },
+            "--tempo" => {
+                settings.tempo_factor = value.parse().ok()
+                    .filter(|factor: &f64| *factor > 0.0)
+                    .ok_or(anyhow!("Tempo factor must be a positive number"))?;
+            },
+            "--subsong" => {
+                subsong = value.parse().map_err(|_| anyhow!("Invalid subsong number {value}"))?;
+            },
+            _ => return Err(anyhow!("Unknown option {arg}")),
+        }
+    }
+
+    let [input, output] = paths.as_slice() else {
+        return Err(anyhow!("Expected an input and an output file"));
+    };
+    let format = Path::new(output).extension()
+        .and_then(|extension| AudioFormat::from_name(&extension.to_string_lossy().to_lowercase()))
+        .unwrap_or(AudioFormat::Wav);
+
+    let data: Arc<[u8]> = fs::read(input)
+        .map_err(|e| anyhow!("Failed to read {input}: {e}"))?
+        .into();
+    let rendered = render(&data, &settings, subsong, format, usize::MAX, f64::INFINITY)?;
+    fs::write(output, rendered).map_err(|e| anyhow!("Failed to write {output}: {e}"))?;
+
+    Ok(())
+}
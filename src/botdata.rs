@@ -20,28 +20,34 @@
 use std::{collections::HashMap, sync::Arc};
 use tokio::sync::RwLock;
 
-use serenity::{all::GuildId, prelude::TypeMapKey};
+use serenity::{all::{GuildId, MessageId}, prelude::TypeMapKey};
 
+use crate::database::Database;
+use crate::pagination::Pages;
 use crate::session::{VoiceSessionData, VoiceSessionHandle};
 
 pub struct BotDataKey;
 
 pub struct BotData {
     pub(crate) sessions: HashMap<GuildId, VoiceSessionHandle>,
-    pub(crate) downloader_client: reqwest::Client
+    pub(crate) downloader_client: reqwest::Client,
+    pub(crate) database: Arc<Database>,
+    pub(crate) paginators: HashMap<MessageId, Pages>
 }
 
 impl TypeMapKey for BotDataKey {
     type Value = BotData;
 }
 
-impl Default for BotData {
-    fn default() -> Self {
+impl BotData {
+    pub fn new(database: Database) -> Self {
         Self {
             sessions: HashMap::new(),
             downloader_client: reqwest::Client::builder()
             .user_agent(format!("ModuloDiscordBot/{} ({} {}) reqwest/0.12.9", env!("CARGO_PKG_VERSION"), std::env::consts::OS, std::env::consts::ARCH))
-            .build().unwrap()
+            .build().unwrap(),
+            database: Arc::new(database),
+            paginators: HashMap::new()
         }
     }
 }
\ No newline at end of file
@@ -43,9 +43,9 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
     let mut session_lock = session.data.write().await;
     let enabled =  mode_string != "none";
     session_lock.amiga_enabled = enabled;
-    if let Some(current_module) = &session_lock.current_module {
+    if let Some(module) = session_lock.current_module.as_ref().and_then(|current_module| current_module.module.openmpt()) {
         let ctl = CString::new("render.resampler.emulate_amiga").unwrap();
-        unsafe { openmpt_module_ctl_set_boolean(current_module.module.0, ctl.as_ptr(), enabled as i32) };
+        unsafe { openmpt_module_ctl_set_boolean(module.0, ctl.as_ptr(), enabled as i32) };
     };
 
     if !enabled {
@@ -55,10 +55,10 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
     };
 
     session_lock.amiga_mode = mode_string.to_owned();
-    if let Some(current_module) = &session_lock.current_module {
+    if let Some(module) = session_lock.current_module.as_ref().and_then(|current_module| current_module.module.openmpt()) {
         let ctl = CString::new("render.resampler.emulate_amiga_type").unwrap();
         let value = CString::new(mode_string).unwrap();
-        unsafe { openmpt_module_ctl_set_text(current_module.module.0, ctl.as_ptr(), value.as_ptr()) };
+        unsafe { openmpt_module_ctl_set_text(module.0, ctl.as_ptr(), value.as_ptr()) };
     };
     drop(session_lock);
 
@@ -0,0 +1,268 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use libopenmpt_sys::{openmpt_module_get_current_speed, openmpt_module_get_current_tempo2, openmpt_module_get_num_channels, openmpt_module_get_num_instruments, openmpt_module_get_num_orders, openmpt_module_get_num_patterns, openmpt_module_get_order_pattern, openmpt_module_get_pattern_num_rows, openmpt_module_get_pattern_row_channel_command, openmpt_module_get_position_seconds, openmpt_module_read_float_mono, OPENMPT_MODULE_COMMAND_EFFECT, OPENMPT_MODULE_COMMAND_INSTRUMENT, OPENMPT_MODULE_COMMAND_NOTE};
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+
+use crate::botdata::BotDataKey;
+use crate::decoder::OpenMptModuleSafe;
+use crate::misc::{followup_command, format_duration, respond_command};
+use crate::pagination::{escape_code_block, followup_paginated, paginate_embed_fields};
+
+/// Notes above this are note off, note cut and note fade
+const NOTE_MAX: u8 = 120;
+/// How many entries of the "most used" lists to show
+const TOP_ENTRIES: usize = 10;
+const MAX_TEMPO_CHANGES: usize = 30;
+/// Speed and tempo are sampled while rendering the module at this rate
+const TIMELINE_SAMPLE_RATE: i32 = 4000;
+const TIMELINE_CHUNK: usize = 200;
+/// Gives up on modules which loop forever or are absurdly long
+const TIMELINE_MAX_SECONDS: f64 = 60.0 * 60.0;
+/// Code blocks are split into several fields to stay below the field length limit
+const FIELD_BODY_MAX_LENGTH: usize = 1000;
+
+#[derive(Default)]
+struct Analysis {
+    title: String,
+    /// Rows played over the order list, with repeated orders counted every time
+    rows_played: u64,
+    notes_per_channel: Vec<u64>,
+    instruments: Vec<(String, u64)>,
+    uses_instruments: bool,
+    effects: Vec<(String, u64)>,
+    /// (position in seconds, speed, tempo)
+    tempo_changes: Vec<(f64, i32, f64)>,
+    num_patterns: i32,
+    patterns_in_orders: usize,
+    patterns_unique: usize,
+}
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    if current_module.module.openmpt().is_none() {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "Only tracker modules can be analyzed").await;
+        return;
+    }
+    let title = current_module.title();
+    let data = current_module.data.clone();
+    drop(session_lock);
+
+    // Walking the patterns and rendering the module takes a while, don't disturb playback
+    interaction.defer(&ctx.http).await.unwrap();
+    let analysis = tokio::task::spawn_blocking(move || {
+        OpenMptModuleSafe::from_memory(&data).map(|scratch| analyze(&scratch))
+    }).await.ok().flatten();
+
+    let Some(mut analysis) = analysis else {
+        followup_command(&ctx, interaction, "Unable to analyze the module").await;
+        return;
+    };
+    analysis.title = title;
+    followup_paginated(&ctx, interaction, analysis_embeds(&analysis)).await;
+}
+
+fn analyze(scratch: &OpenMptModuleSafe) -> Analysis {
+    let num_channels = unsafe { openmpt_module_get_num_channels(scratch.0) };
+    let num_orders = unsafe { openmpt_module_get_num_orders(scratch.0) };
+    let num_patterns = unsafe { openmpt_module_get_num_patterns(scratch.0) };
+    let uses_instruments = unsafe { openmpt_module_get_num_instruments(scratch.0) } > 0;
+
+    let mut analysis = Analysis {
+        notes_per_channel: vec![0; num_channels.max(0) as usize],
+        uses_instruments,
+        num_patterns,
+        ..Default::default()
+    };
+    let mut instrument_usage: HashMap<u8, u64> = HashMap::new();
+    let mut effect_usage: HashMap<String, u64> = HashMap::new();
+    let mut patterns_in_orders: HashSet<i32> = HashSet::new();
+
+    for order in 0..num_orders {
+        let pattern = unsafe { openmpt_module_get_order_pattern(scratch.0, order) };
+        // Separator and end markers
+        if pattern < 0 || pattern >= num_patterns {
+            continue;
+        }
+        patterns_in_orders.insert(pattern);
+
+        let num_rows = unsafe { openmpt_module_get_pattern_num_rows(scratch.0, pattern) };
+        analysis.rows_played += num_rows.max(0) as u64;
+        for row in 0..num_rows {
+            for channel in 0..num_channels {
+                let command = |command: u32| unsafe { openmpt_module_get_pattern_row_channel_command(scratch.0, pattern, row, channel, command as i32) };
+
+                let note = command(OPENMPT_MODULE_COMMAND_NOTE);
+                if note != 0 && note <= NOTE_MAX {
+                    analysis.notes_per_channel[channel as usize] += 1;
+                }
+                let instrument = command(OPENMPT_MODULE_COMMAND_INSTRUMENT);
+                if instrument != 0 {
+                    *instrument_usage.entry(instrument).or_default() += 1;
+                }
+                if command(OPENMPT_MODULE_COMMAND_EFFECT) != 0 {
+                    // Effect numbers are internal to libopenmpt, use the letter the format uses instead
+                    let effect = scratch.format_pattern_command(pattern, row, channel, OPENMPT_MODULE_COMMAND_EFFECT);
+                    *effect_usage.entry(effect).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    // Patterns with the exact same content count once
+    let mut pattern_contents: HashSet<String> = HashSet::new();
+    for pattern in &patterns_in_orders {
+        let num_rows = unsafe { openmpt_module_get_pattern_num_rows(scratch.0, *pattern) };
+        let content: String = (0..num_rows)
+            .flat_map(|row| (0..num_channels).map(move |channel| (row, channel)))
+            .map(|(row, channel)| scratch.format_pattern_cell(*pattern, row, channel))
+            .collect();
+        pattern_contents.insert(content);
+    }
+    analysis.patterns_in_orders = patterns_in_orders.len();
+    analysis.patterns_unique = pattern_contents.len();
+
+    let names = if uses_instruments { scratch.instrument_names() } else { scratch.sample_names() };
+    let mut instruments: Vec<(String, u64)> = instrument_usage.into_iter()
+        .map(|(index, count)| {
+            let name = names.get(index as usize - 1).cloned().unwrap_or_default();
+            (format!("{index:>3} {name}"), count)
+        })
+        .collect();
+    instruments.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    instruments.truncate(TOP_ENTRIES);
+    analysis.instruments = instruments;
+
+    let mut effects: Vec<(String, u64)> = effect_usage.into_iter().collect();
+    effects.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    effects.truncate(TOP_ENTRIES);
+    analysis.effects = effects;
+
+    analysis.tempo_changes = tempo_timeline(scratch);
+
+    analysis
+}
+
+/// Renders the module at a low sample rate, noting every speed or tempo change.
+fn tempo_timeline(scratch: &OpenMptModuleSafe) -> Vec<(f64, i32, f64)> {
+    let mut changes: Vec<(f64, i32, f64)> = Vec::new();
+    let mut buffer = [0f32; TIMELINE_CHUNK];
+    loop {
+        let position = unsafe { openmpt_module_get_position_seconds(scratch.0) };
+        let speed = unsafe { openmpt_module_get_current_speed(scratch.0) };
+        let tempo = unsafe { openmpt_module_get_current_tempo2(scratch.0) };
+        if changes.last().is_none_or(|(_, last_speed, last_tempo)| *last_speed != speed || *last_tempo != tempo) {
+            changes.push((position, speed, tempo));
+        }
+
+        let frames_read = unsafe { openmpt_module_read_float_mono(scratch.0, TIMELINE_SAMPLE_RATE, buffer.len(), buffer.as_mut_ptr()) };
+        if frames_read == 0 || position > TIMELINE_MAX_SECONDS {
+            break;
+        }
+    }
+
+    changes
+}
+
+fn analysis_embeds(analysis: &Analysis) -> Vec<serenity::all::CreateEmbed> {
+    let description = format!(
+        "Patterns: {} in the module, {} in the order list, {} unique by content\nRows played: {}",
+        analysis.num_patterns, analysis.patterns_in_orders, analysis.patterns_unique, analysis.rows_played
+    );
+    let mut fields = Vec::new();
+
+    let rows = analysis.rows_played.max(1) as f64;
+    let density: Vec<String> = analysis.notes_per_channel.iter().enumerate()
+        .map(|(channel, notes)| format!("{:>3} {:>6} notes {:>5.1}%", channel+1, notes, *notes as f64 / rows * 100.0))
+        .collect();
+    fields.extend(code_block_fields("Note density per channel", &density));
+
+    let kind = if analysis.uses_instruments { "instruments" } else { "samples" };
+    let instruments: Vec<String> = analysis.instruments.iter()
+        .map(|(name, count)| format!("{count:>6}x {name}"))
+        .collect();
+    fields.extend(code_block_fields(&format!("Most used {kind}"), &instruments));
+
+    let effects: Vec<String> = analysis.effects.iter()
+        .map(|(effect, count)| format!("{count:>6}x {effect}"))
+        .collect();
+    fields.extend(code_block_fields("Most used effects", &effects));
+
+    let mut tempo_changes: Vec<String> = analysis.tempo_changes.iter()
+        .take(MAX_TEMPO_CHANGES)
+        .map(|(position, speed, tempo)| format!("{:>6} speed {speed:>2}, tempo {tempo:.0}", format_duration(Duration::from_secs_f64(*position))))
+        .collect();
+    if analysis.tempo_changes.len() > MAX_TEMPO_CHANGES {
+        tempo_changes.push(format!("...and {} more", analysis.tempo_changes.len() - MAX_TEMPO_CHANGES));
+    }
+    fields.extend(code_block_fields("Speed and tempo changes", &tempo_changes));
+
+    paginate_embed_fields(&format!("Analysis of {}", analysis.title), &description, fields)
+}
+
+/// Puts the lines into code blocks, split over as many fields as needed.
+fn code_block_fields(name: &str, lines: &[String]) -> Vec<(String, String, bool)> {
+    if lines.is_empty() {
+        return vec![(name.to_owned(), "None".to_owned(), false)];
+    }
+
+    let mut fields = Vec::new();
+    let mut body = String::new();
+    for line in lines {
+        let line = escape_code_block(line);
+        if !body.is_empty() && body.len() + line.len() + 1 > FIELD_BODY_MAX_LENGTH {
+            fields.push(body);
+            body = String::new();
+        }
+        body.push_str(&line);
+        body.push('\n');
+    }
+    fields.push(body);
+
+    fields.into_iter().enumerate()
+        .map(|(i, body)| {
+            let name = if i == 0 { name.to_owned() } else { format!("{name} (continued)") };
+            (name, format!("```\n{body}```"), false)
+        })
+        .collect()
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("analyze").description("Show statistics about the patterns of currently playing module")
+}
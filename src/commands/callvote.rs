@@ -18,10 +18,8 @@
  */
 
 use std::collections::HashMap;
-use std::ffi::{CStr, CString};
 use std::time::Duration;
 
-use libopenmpt_sys::{openmpt_module_get_duration_seconds, openmpt_module_get_metadata, openmpt_module_get_position_seconds};
 use serenity::all::{ButtonStyle, CommandInteraction, CommandOptionType, Context, CreateButton, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, Mentionable, ResolvedValue};
 use serenity::builder::CreateCommand;
 use tokio::spawn;
@@ -29,7 +27,7 @@ use tokio::sync::mpsc::channel;
 use tokio::time::sleep;
 
 use crate::botdata::BotDataKey;
-use crate::misc::respond_command;
+use crate::misc::{escape_markdown, respond_command};
 use crate::vote::{end_vote, Vote, VoteKind};
 
 pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
@@ -102,8 +100,8 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
                 return;
             }
             let current_module = session_lock.current_module.as_ref().unwrap();
-            let position = unsafe { openmpt_module_get_position_seconds(current_module.module.0) };
-            let duration = unsafe { openmpt_module_get_duration_seconds(current_module.module.0) };
+            let position = current_module.module.position_seconds();
+            let duration = current_module.module.duration_seconds();
             if duration-position < 30.0 {
                 drop(session_lock);
                 respond_command(&ctx, interaction, "Less than 30 seconds of this module remaining, can't call a vote of this kind").await;
@@ -135,13 +133,7 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
             }
             let module_to_remove = &session_lock.module_queue[index as usize];
 
-            let title_key = CString::new("title").unwrap();
-            let mut title: String = unsafe {CStr::from_ptr(openmpt_module_get_metadata(module_to_remove.module.0, title_key.as_ptr()))}
-                .to_str().unwrap()
-                .to_string();
-            if title.is_empty() {
-                title = module_to_remove.filename.clone()
-            }
+            let title = escape_markdown(&module_to_remove.title);
 
             VoteKind::RemoveSongFromQueue(index as usize, title)
         },
@@ -0,0 +1,83 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponseFollowup};
+
+use crate::botdata::BotDataKey;
+use crate::misc::{escape_markdown, followup_command, respond_command, upload_limit};
+
+// libopenmpt can only read modules, so converting them into other formats isn't possible.
+// The original file is the only thing that can be exported for now.
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    match interaction.data.options()[0].name {
+        "original" => handle_original(ctx, interaction).await,
+        &_ => respond_command(&ctx, interaction, "Something has gone horribly wrong").await,
+    }
+}
+
+pub async fn handle_original(ctx: Context, interaction: &CommandInteraction) {
+    let guild_id = interaction.guild_id.unwrap();
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&guild_id);
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let data = current_module.data.clone();
+    let title = current_module.title();
+    // Files without a name in the URL still get the right extension
+    let filename = if current_module.filename.is_empty() {
+        format!("module.{}", current_module.module.metadata("type"))
+    } else {
+        current_module.filename.clone()
+    };
+    drop(session_lock);
+
+    if data.len() > upload_limit(&ctx, guild_id) {
+        respond_command(&ctx, interaction, "This module is larger than the upload limit of this server").await;
+        return;
+    }
+
+    // Uploading a large file may take longer than an interaction response is allowed to
+    interaction.defer(&ctx.http).await.unwrap();
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(format!("Original file of **{}**", escape_markdown(&title)))
+        .add_file(CreateAttachment::bytes(data.to_vec(), filename));
+    if let Err(err) = interaction.create_followup(&ctx.http, followup).await {
+        log::warn!("Unable to upload the module: {err}");
+        followup_command(&ctx, interaction, "Unable to upload the module").await;
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("export").description("Export currently playing module")
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "original", "Upload the module file exactly as it was loaded"))
+}
@@ -0,0 +1,170 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, ResolvedOption, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::fx::{EqBand, FxSettings, ReverbSettings, MAX_EQ_BANDS};
+use crate::misc::respond_command;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap()).cloned();
+    drop(data_lock);
+
+    let Some(session) = session_u else {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    };
+    let subcommand = &interaction.data.options()[0];
+    let ResolvedValue::SubCommand(sub_options) = &subcommand.value else { unreachable!() };
+
+    let mut session_lock = session.data.write().await;
+    let mut settings = session_lock.fx.settings().clone();
+    let result = match subcommand.name {
+        "eq" => set_eq(&mut settings, sub_options),
+        "bass" => set_bass(&mut settings, sub_options),
+        "reverb" => set_reverb(&mut settings, sub_options),
+        "limiter" => set_limiter(&mut settings, sub_options),
+        "clear" => {
+            settings = FxSettings::default();
+            Ok("Effects cleared".to_owned())
+        },
+        "show" => Ok("Current effects".to_owned()),
+        &_ => Err("Something has gone horribly wrong".to_owned()),
+    };
+    // Rebuilding restarts the effects, so only do it when something has changed
+    if result.is_ok() && settings != *session_lock.fx.settings() {
+        session_lock.fx.set(settings);
+    }
+    let effects = session_lock.fx.settings().describe();
+    drop(session_lock);
+
+    match result {
+        Ok(message) => respond_command(&ctx, interaction, &format!("{message}\n**Effects:**\n{effects}")).await,
+        Err(message) => respond_command(&ctx, interaction, &message).await,
+    }
+}
+
+fn number_option(options: &[ResolvedOption<'_>], name: &str) -> Option<f64> {
+    options.iter().find_map(|option| match option.value {
+        ResolvedValue::Number(value) if option.name == name => Some(value),
+        ResolvedValue::Integer(value) if option.name == name => Some(value as f64),
+        _ => None,
+    })
+}
+
+fn set_eq(settings: &mut FxSettings, options: &[ResolvedOption<'_>]) -> Result<String, String> {
+    let frequency = number_option(options, "frequency").unwrap() as f32;
+    let gain_db = number_option(options, "gain").unwrap() as f32;
+    let q = number_option(options, "q").unwrap_or(1.0) as f32;
+
+    // A band at the same frequency gets replaced, 0 dB removes it
+    settings.eq_bands.retain(|band| band.frequency != frequency);
+    if gain_db == 0.0 {
+        return Ok(format!("EQ band at **{frequency} Hz** removed"));
+    }
+    if settings.eq_bands.len() >= MAX_EQ_BANDS {
+        return Err(format!("There can be at most {MAX_EQ_BANDS} EQ bands, set the gain of one to 0 to remove it"));
+    }
+    settings.eq_bands.push(EqBand { frequency, gain_db, q });
+    settings.eq_bands.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+    Ok(format!("EQ band at **{frequency} Hz** set to **{gain_db:+.1} dB**"))
+}
+
+fn set_bass(settings: &mut FxSettings, options: &[ResolvedOption<'_>]) -> Result<String, String> {
+    settings.bass_boost_db = number_option(options, "gain").unwrap() as f32;
+    if settings.bass_boost_db == 0.0 {
+        Ok("Bass boost turned off".to_owned())
+    } else {
+        Ok(format!("Bass boost set to **{:+.1} dB**", settings.bass_boost_db))
+    }
+}
+
+fn set_reverb(settings: &mut FxSettings, options: &[ResolvedOption<'_>]) -> Result<String, String> {
+    let mix = number_option(options, "mix").unwrap();
+    let room_size = number_option(options, "room").unwrap_or(50.0);
+    if mix == 0.0 {
+        settings.reverb = None;
+        return Ok("Reverb turned off".to_owned());
+    }
+    settings.reverb = Some(ReverbSettings { mix: mix as f32 / 100.0, room_size: room_size as f32 / 100.0 });
+    Ok(format!("Reverb set to **{mix}%** mix, **{room_size}%** room size"))
+}
+
+fn set_limiter(settings: &mut FxSettings, options: &[ResolvedOption<'_>]) -> Result<String, String> {
+    let ResolvedValue::Boolean(enabled) = options[0].value else { unreachable!() };
+    settings.limiter = enabled;
+    let state = if enabled { "on" } else { "off" };
+    Ok(format!("Limiter turned **{state}**"))
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("fx").description("Change the effects applied to this session's output")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "eq", "Boost or cut a frequency band")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "frequency", "Center frequency in Hz")
+                        .min_int_value(20)
+                        .max_int_value(20000)
+                        .required(true)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Number, "gain", "Gain in dB, 0 removes the band")
+                        .min_number_value(-24.0)
+                        .max_number_value(24.0)
+                        .required(true)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Number, "q", "Width of the band, higher is narrower, 1 by default")
+                        .min_number_value(0.1)
+                        .max_number_value(10.0)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "bass", "Boost the bass")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Number, "gain", "Gain in dB, 0 turns it off")
+                        .min_number_value(0.0)
+                        .max_number_value(18.0)
+                        .required(true)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "reverb", "Add reverb")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "mix", "How much of the output is reverb, 0% turns it off")
+                        .min_int_value(0)
+                        .max_int_value(100)
+                        .required(true)
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "room", "Room size, 50% by default")
+                        .min_int_value(0)
+                        .max_int_value(100)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "limiter", "Keep loud modules from clipping")
+                .add_sub_option(CreateCommandOption::new(CommandOptionType::Boolean, "enabled", "Whether the limiter is on").required(true))
+        )
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "clear", "Turn off all effects"))
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "show", "Show the current effects"))
+}
@@ -17,30 +17,18 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::ffi::{CStr, CString};
-
 use libopenmpt_sys::{
-    openmpt_module_get_current_order, openmpt_module_get_current_pattern, openmpt_module_get_current_row, openmpt_module_get_current_speed, openmpt_module_get_current_tempo2, openmpt_module_get_duration_seconds, openmpt_module_get_metadata, openmpt_module_get_num_channels, openmpt_module_get_num_instruments, openmpt_module_get_num_orders, openmpt_module_get_num_patterns, openmpt_module_get_num_samples, openmpt_module_get_num_subsongs, openmpt_module_get_position_seconds
+    openmpt_module_get_current_order, openmpt_module_get_current_pattern, openmpt_module_get_current_row, openmpt_module_get_current_speed, openmpt_module_get_current_tempo2, openmpt_module_get_num_channels, openmpt_module_get_num_instruments, openmpt_module_get_num_orders, openmpt_module_get_num_patterns, openmpt_module_get_num_samples, openmpt_module_get_num_subsongs
 };
 use serenity::all::{CommandInteraction, Context};
 use serenity::builder::CreateCommand;
 
 use crate::botdata::BotDataKey;
-use crate::misc::{followup_command, format_duration, respond_command};
-
-const METADATA_KEYS: [&str; 11] = [
-    "type",
-    "type_long",
-    "originaltype",
-    "originaltype_long",
-    "container",
-    "container_long",
-    "tracker",
-    "artist",
-    "title",
-    "date",
-    "warnings",
-];
+use crate::misc::{format_duration, respond_command};
+use crate::pagination::{escape_code_block, paginate_embed_fields, respond_paginated, truncate};
+
+/// Values longer than this don't get an inline field
+const INLINE_MAX_LENGTH: usize = 40;
 
 pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
     let data_lock = ctx.data.read().await;
@@ -52,74 +40,83 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
     }
 
     let session = session_u.unwrap().clone();
+    drop(data_lock);
     let session_lock = session.data.read().await;
 
     if let Some(current_module) = &session_lock.current_module {
         // Title
-        let title_key = CString::new("title").unwrap();
-        let mut title: String = unsafe {CStr::from_ptr(openmpt_module_get_metadata(current_module.module.0, title_key.as_ptr()))}
-            .to_str().unwrap()
-            .to_string();
-        if title.is_empty() {
-            title = current_module.filename.clone()
+        let title = current_module.title();
+        let mut fields: Vec<(String, String, bool)> = Vec::new();
+
+        // Load warnings go first so they're not missed
+        let warnings = current_module.module.metadata("warnings");
+        if !warnings.is_empty() {
+            fields.push(("⚠️ Load warnings".to_owned(), warnings, false));
         }
 
-        // Details
-        let num_channels = unsafe {openmpt_module_get_num_channels(current_module.module.0)};
-        let num_instruments = unsafe {openmpt_module_get_num_instruments(current_module.module.0)};
-        let num_orders = unsafe {openmpt_module_get_num_orders(current_module.module.0)};
-        let num_patterns = unsafe {openmpt_module_get_num_patterns(current_module.module.0)};
-        let num_samples = unsafe {openmpt_module_get_num_samples(current_module.module.0)};
-        let num_subsongs = unsafe {openmpt_module_get_num_subsongs(current_module.module.0)};
-
-        let details = String::new() +
-            "## Module details\n"+
-            "Channels: "+&num_channels.to_string()+"\n"+
-            "Instruments: "+&num_instruments.to_string()+"\n"+
-            "Orders: "+&num_orders.to_string()+"\n"+
-            "Patterns: "+&num_patterns.to_string()+"\n"+
-            "Samples: "+&num_samples.to_string()+"\n"+
-            "Subsongs: "+&num_subsongs.to_string();
+        // Details and playback state, tracker modules only
+        let mut playback = String::new();
+        if let Some(module) = current_module.module.openmpt() {
+            let details = unsafe {[
+                ("Channels", openmpt_module_get_num_channels(module.0)),
+                ("Instruments", openmpt_module_get_num_instruments(module.0)),
+                ("Orders", openmpt_module_get_num_orders(module.0)),
+                ("Patterns", openmpt_module_get_num_patterns(module.0)),
+                ("Samples", openmpt_module_get_num_samples(module.0)),
+                ("Subsongs", openmpt_module_get_num_subsongs(module.0)),
+            ]};
+            for (name, value) in details {
+                fields.push((name.to_owned(), value.to_string(), true));
+            }
+
+            let order = unsafe {openmpt_module_get_current_order(module.0)};
+            let pattern = unsafe {openmpt_module_get_current_pattern(module.0)};
+            let row = unsafe {openmpt_module_get_current_row(module.0)};
+            let speed = unsafe {openmpt_module_get_current_speed(module.0)};
+            let tempo = unsafe {openmpt_module_get_current_tempo2(module.0)};
+
+            playback = playback +
+                &format!("Row {}, order {} (pattern {})", row, order, pattern)+"\n"+
+                &format!("Speed/tempo: {}/{}", speed, tempo)+"\n";
+        }
 
         // Playback
-        let position_sec = unsafe {openmpt_module_get_position_seconds(current_module.module.0)};
+        let position_sec = current_module.module.position_seconds();
         let position = std::time::Duration::from_secs_f64(position_sec);
         let position_formatted = format_duration(position);
 
-        let duration_sec = unsafe {openmpt_module_get_duration_seconds(current_module.module.0)};
+        let duration_sec = current_module.module.duration_seconds();
         let duration = std::time::Duration::from_secs_f64(duration_sec);
         let duration_formatted = format_duration(duration);
 
-        let order = unsafe {openmpt_module_get_current_order(current_module.module.0)};
-        let pattern = unsafe {openmpt_module_get_current_pattern(current_module.module.0)};
-        // let playing_channels = unsafe {openmpt_module_get_current_playing_channels(current_module.module.0)};
-        let row = unsafe {openmpt_module_get_current_row(current_module.module.0)};
-        let speed = unsafe {openmpt_module_get_current_speed(current_module.module.0)};
-        let tempo = unsafe {openmpt_module_get_current_tempo2(current_module.module.0)};
-
-        let playback = String::new() +
-            "## Playback details\n"+
-            &format!("Row {}, order {} (pattern {})", row, order, pattern)+"\n"+
-            // "Playing_channels: "+&playing_channels.to_string()+"\n"+
-            &format!("Speed/tempo: {}/{}", speed, tempo)+"\n"+
-            &format!("Position/duration: {}/{}", position_formatted, duration_formatted);
-
-        // Metadata
-        let mut metadata = "## Metadata\n".to_string();
-        for key in METADATA_KEYS {
-            let raw_key = CString::new(key).unwrap();
-            let value: String = unsafe {CStr::from_ptr(openmpt_module_get_metadata(current_module.module.0, raw_key.as_ptr()))}
-                .to_str().unwrap()
-                .to_string();
-
-            if !value.is_empty() {
-                metadata.push_str(&(key.to_string()+": "+&value+"\n"));
+        playback.push_str(&format!("Position/duration: {}/{}", position_formatted, duration_formatted));
+
+        // Metadata, every key the decoder knows about
+        for key in current_module.module.metadata_keys() {
+            if key == "warnings" {
+                continue;
+            }
+            let value = current_module.module.metadata(&key);
+            if value.is_empty() {
+                continue;
+            }
+
+            if value.contains('\n') {
+                // Keep the layout of song messages and such
+                let value = format!("```\n{}```", truncate(&escape_code_block(&value), 1000));
+                fields.push((key, value, false));
+            } else {
+                let inline = value.len() <= INLINE_MAX_LENGTH;
+                fields.push((key, value, inline));
             }
         }
 
+        if !session_lock.fx.settings().is_empty() {
+            fields.push(("🎛️ Effects".to_owned(), session_lock.fx.settings().describe(), false));
+        }
+
         drop(session_lock);
-        let response = format!("# {title}\n{details}\n{playback}\n{metadata}");
-        respond_command(&ctx, interaction, &response).await;
+        respond_paginated(&ctx, interaction, paginate_embed_fields(&title, &playback, fields)).await;
         return;
     } else {
         drop(session_lock);
@@ -130,4 +127,4 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
 
 pub fn register() -> CreateCommand {
     CreateCommand::new("info").description("Print info about currently playing module")
-}
\ No newline at end of file
+}
@@ -0,0 +1,64 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+
+use crate::botdata::BotDataKey;
+use crate::misc::respond_command;
+use crate::pagination::{paginate_code_block, respond_paginated};
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let names = current_module.module.openmpt()
+        .map(|module| module.instrument_names())
+        .unwrap_or_default();
+    drop(session_lock);
+
+    if names.is_empty() {
+        respond_command(&ctx, interaction, "This module has no instruments").await;
+        return;
+    }
+
+    // Trackers number instruments starting from 1
+    let lines: Vec<String> = names.iter().enumerate()
+        .map(|(i, name)| format!("{:>3} {}", i+1, name))
+        .collect();
+    let pages = paginate_code_block(&format!("## Instruments ({})", names.len()), &lines);
+    respond_paginated(&ctx, interaction, pages).await;
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("instruments").description("List instrument names of currently playing module")
+}
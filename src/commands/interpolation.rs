@@ -36,21 +36,14 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
             interaction.data.options()[0].name
         } else { unreachable!() }
     };
-    let interpolation = match interpolation_string {
-        "default" => Interpolation::Default,
-        "none" => Interpolation::None,
-        "linear" => Interpolation::Linear,
-        "cubic" => Interpolation::Cubic,
-        "sinc8" => Interpolation::Sinc8,
-        &_ => unreachable!()
-    };
+    let interpolation = Interpolation::from_name(interpolation_string).unwrap();
 
     let session = session_u.cloned().unwrap();
     let mut session_lock = session.data.write().await;
     session_lock.interpolation = interpolation;
-    if let Some(current_module) = &mut session_lock.current_module {
+    if let Some(module) = session_lock.current_module.as_ref().and_then(|current_module| current_module.module.openmpt()) {
         unsafe {openmpt_module_set_render_param(
-            current_module.module.0,
+            module.0,
             OPENMPT_MODULE_RENDER_INTERPOLATIONFILTER_LENGTH as std::os::raw::c_int,
             session_lock.interpolation.to_openmpt_value())
         };
@@ -17,31 +17,17 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use serenity::all::{CacheHttp, ChannelId, ChannelType, CommandInteraction, Context, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::all::{CacheHttp, ChannelType, CommandInteraction, Context, CreateInteractionResponse, CreateInteractionResponseMessage};
 use serenity::builder::CreateCommand;
 use songbird::input::RawAdapter;
 
 use crate::botdata::BotDataKey;
-use crate::misc::respond_command;
+use crate::misc::{respond_command, user_voice_channel};
 use crate::session::{initiate_session, VoiceSession};
 
 pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
-    let (guild_id, voice_channel_id) = {
-        let guild_id = interaction.guild_id.unwrap();
-        let guild = ctx.cache.guild(guild_id).unwrap();
-        let voicestate_u = guild.voice_states.get(&interaction.member.clone().unwrap().user.id);
-        let voice_channel_id: Option<ChannelId> = {
-            if let Some(voicestate) = voicestate_u {
-                voicestate.channel_id
-            } else {
-                None
-            }
-        };
-
-        (guild_id, voice_channel_id)
-    };
-
-    let connect_to = match voice_channel_id {
+    let guild_id = interaction.guild_id.unwrap();
+    let connect_to = match user_voice_channel(&ctx, interaction) {
         Some(channel) => channel,
         None => {
             let response = CreateInteractionResponse::Message(
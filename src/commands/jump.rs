@@ -17,7 +17,7 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use libopenmpt_sys::{openmpt_module_get_current_order, openmpt_module_select_subsong, openmpt_module_set_position_order_row};
+use libopenmpt_sys::{openmpt_module_get_current_order, openmpt_module_set_position_order_row};
 use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommandOption, ResolvedOption, ResolvedValue};
 use serenity::builder::CreateCommand;
 
@@ -58,8 +58,13 @@ pub async fn handle_order(ctx: Context, interaction: &CommandInteraction, option
     let session_lock = session.data.write().await;
 
     if let Some(current_module) = &session_lock.current_module {
-        unsafe { openmpt_module_set_position_order_row(current_module.module.0, order as i32, 0) };
-        let current_order = unsafe { openmpt_module_get_current_order(current_module.module.0) };
+        let Some(module) = current_module.module.openmpt() else {
+            drop(session_lock);
+            respond_command(&ctx, interaction, "This module has no orders").await;
+            return;
+        };
+        unsafe { openmpt_module_set_position_order_row(module.0, order as i32, 0) };
+        let current_order = unsafe { openmpt_module_get_current_order(module.0) };
         if current_order != (order as i32) {
             drop(session_lock);
             respond_command(&ctx, interaction, "The specified order number is out of range").await;
@@ -87,11 +92,10 @@ pub async fn handle_subsong(ctx: Context, interaction: &CommandInteraction, opti
     let ResolvedValue::Integer(subsong) = subsong_u else { unreachable!() };
 
     let session = session_u.unwrap().clone();
-    let session_lock = session.data.write().await;
+    let mut session_lock = session.data.write().await;
 
-    if let Some(current_module) = &session_lock.current_module {
-        let subsong_status = unsafe { openmpt_module_select_subsong(current_module.module.0, subsong as i32) };
-        if subsong_status == 0 {
+    if let Some(current_module) = &mut session_lock.current_module {
+        if !current_module.module.select_subsong(subsong as i32) {
             drop(session_lock);
             respond_command(&ctx, interaction, "The specified subsong number is out of range").await;
             return;
@@ -17,14 +17,12 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::ffi::{CStr, CString};
-
-use libopenmpt_sys::openmpt_module_get_metadata;
-use serenity::all::{CommandInteraction, Context, CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedValue};
 use serenity::builder::CreateCommand;
 
 use crate::botdata::BotDataKey;
 use crate::misc::respond_command;
+use crate::pagination::{paginate_code_block, respond_paginated};
 
 pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
     let data_lock = ctx.data.read().await;
@@ -35,38 +33,40 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
         return;
     }
 
+    let as_file = interaction.data.options().iter()
+        .any(|option| option.name == "file" && matches!(option.value, ResolvedValue::Boolean(true)));
+
     let session = session_u.unwrap().clone();
+    drop(data_lock);
     let session_lock = session.data.read().await;
 
     if let Some(current_module) = &session_lock.current_module {
-        let mut song_message = "## Song message\n```".to_string();
-        let message_key = CString::new("message").unwrap();
-        let msg: String = unsafe {CStr::from_ptr(openmpt_module_get_metadata(current_module.module.0, message_key.as_ptr()))}
-            .to_str().unwrap()
-            .to_string()
-            .replace("`", "\\`");
-        song_message.push_str(&(msg.clone()+&"```"));
-
+        // Song messages come from all sorts of systems, normalize the line endings
+        let message = current_module.module.metadata("message")
+            .replace("\r\n", "\n")
+            .replace('\r', "\n");
         drop(session_lock);
 
-        let response = CreateInteractionResponse::Message(
-            CreateInteractionResponseMessage::new()
-            .content(song_message)
-        );
-        let result = interaction.create_response(&ctx.http, response).await;
-        if let Err(err) = result {
-            match err {
-                serenity::Error::Model(model_err) => {
-                    if matches!(model_err, serenity::all::ModelError::MessageTooLong(_)) {
-                        respond_command(&ctx, interaction, "We've reached Discord's character limit. Sorry.").await
-                    } else {
-                        respond_command(&ctx, interaction, &("Error: ".to_owned() + &model_err.to_string())).await
-                    }
-                },
-                _ => log::warn!("Unable to send response: {err}")
+        if message.trim().is_empty() {
+            respond_command(&ctx, interaction, "This module has no song message").await;
+            return;
+        }
+
+        if as_file {
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                .content("## Song message")
+                .add_file(CreateAttachment::bytes(message.into_bytes(), "message.txt"))
+            );
+            if let Err(err) = interaction.create_response(&ctx.http, response).await {
+                log::warn!("Unable to send response: {err}");
             }
+            return;
         }
 
+        // Code blocks keep the fixed-width layout of text art
+        let lines: Vec<String> = message.lines().map(str::to_owned).collect();
+        respond_paginated(&ctx, interaction, paginate_code_block("## Song message", &lines)).await;
         return;
     } else {
         drop(session_lock);
@@ -77,4 +77,5 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
 
 pub fn register() -> CreateCommand {
     CreateCommand::new("message").description("Print currently playing module's song message")
-}
\ No newline at end of file
+        .add_option(CreateCommandOption::new(CommandOptionType::Boolean, "file", "Attach the message as a text file instead"))
+}
@@ -32,6 +32,23 @@ mod interpolation;
 mod amigaresampler;
 mod readme;
 mod autosubsong;
+mod playlist;
+mod samples;
+mod instruments;
+mod pattern;
+mod vu;
+mod orders;
+mod subsongs;
+mod analyze;
+mod stereo;
+mod render;
+mod waveform;
+mod scope;
+mod sample;
+mod stems;
+mod export;
+mod record;
+mod fx;
 
 use std::sync::Arc;
 use serenity::{all::{Command, CommandInteraction, Context, GuildId, Http}, Error};
@@ -52,6 +69,23 @@ pub async fn register_commands(http: &Arc<Http>) -> Result<Vec<Command>, Error>
     Command::create_global_command(http, amigaresampler::register()).await.unwrap();
     Command::create_global_command(http, readme::register()).await.unwrap();
     Command::create_global_command(http, autosubsong::register()).await.unwrap();
+    Command::create_global_command(http, playlist::register()).await.unwrap();
+    Command::create_global_command(http, samples::register()).await.unwrap();
+    Command::create_global_command(http, instruments::register()).await.unwrap();
+    Command::create_global_command(http, pattern::register()).await.unwrap();
+    Command::create_global_command(http, vu::register()).await.unwrap();
+    Command::create_global_command(http, orders::register()).await.unwrap();
+    Command::create_global_command(http, subsongs::register()).await.unwrap();
+    Command::create_global_command(http, analyze::register()).await.unwrap();
+    Command::create_global_command(http, stereo::register()).await.unwrap();
+    Command::create_global_command(http, render::register()).await.unwrap();
+    Command::create_global_command(http, waveform::register()).await.unwrap();
+    Command::create_global_command(http, scope::register()).await.unwrap();
+    Command::create_global_command(http, sample::register()).await.unwrap();
+    Command::create_global_command(http, stems::register()).await.unwrap();
+    Command::create_global_command(http, export::register()).await.unwrap();
+    Command::create_global_command(http, record::register()).await.unwrap();
+    Command::create_global_command(http, fx::register()).await.unwrap();
 
     /*
         To anybody who comes across this line:
@@ -77,6 +111,23 @@ pub async fn register_commands(http: &Arc<Http>) -> Result<Vec<Command>, Error>
             amigaresampler::register(),
             readme::register(),
             autosubsong::register(),
+            playlist::register(),
+            samples::register(),
+            instruments::register(),
+            pattern::register(),
+            vu::register(),
+            orders::register(),
+            subsongs::register(),
+            analyze::register(),
+            stereo::register(),
+            render::register(),
+            waveform::register(),
+            scope::register(),
+            sample::register(),
+            stems::register(),
+            export::register(),
+            record::register(),
+            fx::register(),
         ])
         .await
 }
@@ -98,6 +149,23 @@ pub async fn handle_commands(ctx: Context, interaction: &CommandInteraction) {
         "amigaresampler" => amigaresampler::handle(ctx, interaction).await,
         "readme" => readme::handle(ctx, interaction).await,
         "autosubsong" => autosubsong::handle(ctx, interaction).await,
+        "playlist" => playlist::handle(ctx, interaction).await,
+        "samples" => samples::handle(ctx, interaction).await,
+        "instruments" => instruments::handle(ctx, interaction).await,
+        "pattern" => pattern::handle(ctx, interaction).await,
+        "vu" => vu::handle(ctx, interaction).await,
+        "orders" => orders::handle(ctx, interaction).await,
+        "subsongs" => subsongs::handle(ctx, interaction).await,
+        "analyze" => analyze::handle(ctx, interaction).await,
+        "stereo" => stereo::handle(ctx, interaction).await,
+        "render" => render::handle(ctx, interaction).await,
+        "waveform" => waveform::handle(ctx, interaction).await,
+        "scope" => scope::handle(ctx, interaction).await,
+        "sample" => sample::handle(ctx, interaction).await,
+        "stems" => stems::handle(ctx, interaction).await,
+        "export" => export::handle(ctx, interaction).await,
+        "record" => record::handle(ctx, interaction).await,
+        "fx" => fx::handle(ctx, interaction).await,
         &_ => {},
     };
 }
\ No newline at end of file
@@ -0,0 +1,121 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Duration;
+
+use libopenmpt_sys::{openmpt_module_get_current_order, openmpt_module_get_num_orders, openmpt_module_get_num_patterns, openmpt_module_get_order_pattern, openmpt_module_get_pattern_num_rows, openmpt_module_get_selected_subsong, openmpt_module_select_subsong, openmpt_module_set_position_order_row};
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+
+use crate::botdata::BotDataKey;
+use crate::decoder::OpenMptModuleSafe;
+use crate::misc::{followup_command, format_duration, respond_command};
+use crate::pagination::{followup_paginated, paginate_code_block};
+
+struct OrderEntry {
+    pattern: i32,
+    num_rows: i32,
+    name: String,
+    /// None for separator and end markers
+    start: Option<f64>,
+}
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let Some(module) = current_module.module.openmpt() else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no orders").await;
+        return;
+    };
+    let current_order = unsafe { openmpt_module_get_current_order(module.0) };
+    let subsong = unsafe { openmpt_module_get_selected_subsong(module.0) };
+    let data = current_module.data.clone();
+    drop(session_lock);
+
+    // Seeking around the playing instance would disturb playback, use a separate one
+    interaction.defer(&ctx.http).await.unwrap();
+    let entries = tokio::task::spawn_blocking(move || {
+        OpenMptModuleSafe::from_memory(&data).map(|scratch| order_entries(&scratch, subsong))
+    }).await.ok().flatten().unwrap_or_default();
+
+    let lines: Vec<String> = entries.iter().enumerate()
+        .map(|(order, entry)| {
+            let marker = if order as i32 == current_order { '>' } else { ' ' };
+            match entry.start {
+                Some(start) => format!(
+                    "{marker}{order:>3}  pattern {:>3}  {:>3} rows  {:>6}  {}",
+                    entry.pattern,
+                    entry.num_rows,
+                    format_duration(Duration::from_secs_f64(start)),
+                    entry.name
+                ),
+                None => format!("{marker}{order:>3}  ---"),
+            }
+        })
+        .collect();
+
+    if lines.is_empty() {
+        followup_command(&ctx, interaction, "This module has no orders").await;
+        return;
+    }
+    let pages = paginate_code_block(&format!("## Orders ({})", lines.len()), &lines);
+    followup_paginated(&ctx, interaction, pages).await;
+}
+
+/// Positions are only meaningful within the selected subsong, orders outside of it start at 0:00.
+fn order_entries(scratch: &OpenMptModuleSafe, subsong: i32) -> Vec<OrderEntry> {
+    unsafe { openmpt_module_select_subsong(scratch.0, subsong) };
+    let num_orders = unsafe { openmpt_module_get_num_orders(scratch.0) };
+    let num_patterns = unsafe { openmpt_module_get_num_patterns(scratch.0) };
+
+    (0..num_orders)
+        .map(|order| {
+            let pattern = unsafe { openmpt_module_get_order_pattern(scratch.0, order) };
+            if pattern < 0 || pattern >= num_patterns {
+                return OrderEntry { pattern, num_rows: 0, name: String::new(), start: None };
+            }
+
+            OrderEntry {
+                pattern,
+                num_rows: unsafe { openmpt_module_get_pattern_num_rows(scratch.0, pattern) },
+                name: scratch.pattern_name(pattern),
+                start: Some(unsafe { openmpt_module_set_position_order_row(scratch.0, order, 0) }),
+            }
+        })
+        .collect()
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("orders").description("List orders of currently playing module")
+}
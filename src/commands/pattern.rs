@@ -0,0 +1,156 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use libopenmpt_sys::{openmpt_module_get_current_order, openmpt_module_get_current_pattern, openmpt_module_get_current_row, openmpt_module_get_num_channels, openmpt_module_get_pattern_num_rows};
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, EditInteractionResponse, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::decoder::OpenMptModuleSafe;
+use crate::misc::respond_command;
+use crate::pagination::escape_code_block;
+
+/// Rows shown above and below the current one
+const ROWS_AROUND: i32 = 8;
+/// Code blocks wider than this wrap on most screens
+const MAX_LINE_WIDTH: usize = 100;
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(3);
+/// Interaction responses can only be edited for 15 minutes
+const FOLLOW_DURATION: Duration = Duration::from_secs(10 * 60);
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+
+    let mut first_channel = 0;
+    let mut follow = false;
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("channel", ResolvedValue::Integer(channel)) => first_channel = (channel - 1).max(0) as i32,
+            ("follow", ResolvedValue::Boolean(value)) => follow = value,
+            _ => {}
+        }
+    }
+
+    let session_lock = session.data.read().await;
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let Some(module) = current_module.module.openmpt() else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no patterns").await;
+        return;
+    };
+    let filehash = current_module.filehash.clone();
+    let view = render_pattern(module, first_channel);
+    drop(session_lock);
+
+    let Some(view) = view else {
+        respond_command(&ctx, interaction, "The specified channel is out of range").await;
+        return;
+    };
+    if !follow {
+        respond_command(&ctx, interaction, &view).await;
+        return;
+    }
+    respond_command(&ctx, interaction, &format!("{view}\n-# Following playback")).await;
+
+    let mut view = view;
+    let started = Instant::now();
+    while started.elapsed() < FOLLOW_DURATION {
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+
+        let session_lock = session.data.read().await;
+        // Stop once the module has changed
+        let Some(new_view) = session_lock.current_module.as_ref()
+            .filter(|current_module| current_module.filehash == filehash)
+            .and_then(|current_module| current_module.module.openmpt())
+            .and_then(|module| render_pattern(module, first_channel)) else { break };
+        drop(session_lock);
+        view = new_view;
+
+        let response = EditInteractionResponse::new().content(format!("{view}\n-# Following playback"));
+        if interaction.edit_response(&ctx.http, response).await.is_err() {
+            return;
+        }
+    }
+
+    // Leave the last view, without the note
+    let _ = interaction.edit_response(&ctx.http, EditInteractionResponse::new().content(view)).await;
+}
+
+/// Renders the rows around the current one, with as many channels as fit into a line.
+/// Returns None if the first channel is out of range.
+fn render_pattern(module: &OpenMptModuleSafe, first_channel: i32) -> Option<String> {
+    let num_channels = unsafe { openmpt_module_get_num_channels(module.0) };
+    if first_channel >= num_channels {
+        return None;
+    }
+    let order = unsafe { openmpt_module_get_current_order(module.0) };
+    let pattern = unsafe { openmpt_module_get_current_pattern(module.0) };
+    let current_row = unsafe { openmpt_module_get_current_row(module.0) };
+    let num_rows = unsafe { openmpt_module_get_pattern_num_rows(module.0, pattern) };
+
+    // Every cell has the same width, see format_pattern_cell
+    let cell_width = module.format_pattern_cell(pattern, 0, first_channel).chars().count().max(1);
+    let row_number_width = 6;
+    let visible_channels = ((MAX_LINE_WIDTH - row_number_width) / (cell_width + 1)).max(1) as i32;
+    let last_channel = (first_channel + visible_channels).min(num_channels);
+
+    let mut view = format!(
+        "## Order {order}, pattern {pattern}, row {current_row}\nChannels {}-{} of {num_channels}\n```\n",
+        first_channel + 1,
+        last_channel
+    );
+    // Keep the current row in the middle, so the view doesn't jump around while following playback
+    for row in current_row - ROWS_AROUND..=current_row + ROWS_AROUND {
+        if row < 0 || row >= num_rows {
+            view.push('\n');
+            continue;
+        }
+        let marker = if row == current_row { '>' } else { ' ' };
+        view.push_str(&format!("{marker}{row:>3} |"));
+        for channel in first_channel..last_channel {
+            view.push_str(&escape_code_block(&module.format_pattern_cell(pattern, row, channel)));
+            view.push('|');
+        }
+        view.push('\n');
+    }
+    view.push_str("```");
+
+    Some(view)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("pattern").description("Show the pattern rows around the current position")
+        .add_option(CreateCommandOption::new(CommandOptionType::Integer, "channel", "First channel to show")
+            .min_int_value(1)
+        )
+        .add_option(CreateCommandOption::new(CommandOptionType::Boolean, "follow", "Keep updating the view while the module plays"))
+}
@@ -0,0 +1,386 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use futures::StreamExt;
+use reqwest::Url;
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage, ResolvedOption, ResolvedValue};
+use serenity::builder::CreateCommand;
+use tokio::task::spawn_blocking;
+
+use crate::botdata::BotDataKey;
+use crate::database::PlaylistEntry;
+use crate::misc::{download_file, escape_markdown, is_discord_attachment, followup_command, respond_command, user_voice_channel};
+use crate::session::{initiate_session, preload_next_in_queue, QueuedModule};
+
+/// How many playlist entries are downloaded at the same time
+const PLAYLIST_WORKERS: usize = 4;
+/// Playlists longer than this are cut off
+const PLAYLIST_MAX_ENTRIES: usize = 100;
+/// Leave some room below Discord's 2000 character limit for the summary line
+const SUMMARY_MAX_LENGTH: usize = 1900;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    if let ResolvedValue::SubCommand(sub_options) = &interaction.data.options()[0].value {
+        match interaction.data.options()[0].name {
+            "load" => handle_load(ctx, interaction, sub_options).await,
+            "save" => handle_save(ctx, interaction, sub_options).await,
+            "play" => handle_play(ctx, interaction, sub_options).await,
+            "list" => handle_list(ctx, interaction).await,
+            "delete" => handle_delete(ctx, interaction, sub_options).await,
+            &_ => { respond_command(&ctx, interaction, "Something has gone horribly wrong").await; return; }
+        }
+    }
+}
+
+pub async fn handle_load(ctx: Context, interaction: &CommandInteraction, options: &Vec<ResolvedOption<'_>>) {
+    let url = options.iter().find_map(|option| match (option.name, &option.value) {
+        ("url", ResolvedValue::String(url)) => Some(url.to_string()),
+        _ => None,
+    });
+    let file = options.iter().find_map(|option| match (option.name, &option.value) {
+        ("file", ResolvedValue::Attachment(attachment)) => Some(attachment.url.clone()),
+        _ => None,
+    });
+    let playlist_url = match (url, file) {
+        (Some(url), None) => url,
+        (None, Some(file)) => file,
+        (Some(_), Some(_)) => {
+            respond_command(&ctx, interaction, "Provide either a playlist URL or a playlist file, not both").await;
+            return;
+        },
+        (None, None) => {
+            respond_command(&ctx, interaction, "Provide either a playlist URL or a playlist file").await;
+            return;
+        },
+    };
+
+    if !join_and_defer(&ctx, interaction).await {
+        return;
+    }
+
+    let client = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .downloader_client.clone();
+    let playlist_bytes = match download_file(&client, &playlist_url).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            followup_command(&ctx, interaction, &err.to_string()).await;
+            return;
+        },
+    };
+    let base_url = Url::parse(&playlist_url).unwrap();
+    let entries = parse_playlist(&String::from_utf8_lossy(&playlist_bytes), &base_url);
+    if entries.is_empty() {
+        followup_command(&ctx, interaction, "The playlist has no entries").await;
+        return;
+    }
+
+    let entries = entries.into_iter()
+        .map(|url| (url, None))
+        .collect();
+    enqueue_entries(&ctx, interaction, entries).await;
+}
+
+pub async fn handle_save(ctx: Context, interaction: &CommandInteraction, options: &Vec<ResolvedOption<'_>>) {
+    let ResolvedValue::String(name) = options[0].value else { unreachable!() };
+    let guild_id = interaction.guild_id.unwrap();
+
+    let data_lock = ctx.data.read().await;
+    let botdata = data_lock.get::<BotDataKey>().unwrap();
+    let Some(session) = botdata.sessions.get(&guild_id).cloned() else {
+        drop(data_lock);
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    };
+    let database = botdata.database.clone();
+    drop(data_lock);
+
+    let session_lock = session.data.read().await;
+    let entries: Vec<PlaylistEntry> = session_lock.current_module.iter()
+        .map(|module| PlaylistEntry { url: module.url.clone(), filehash: module.filehash.clone() })
+        .chain(session_lock.module_queue.iter()
+            .map(|module| PlaylistEntry { url: module.url.clone(), filehash: module.filehash.clone() }))
+        .collect();
+    drop(session_lock);
+
+    if entries.is_empty() {
+        respond_command(&ctx, interaction, "Nothing is playing and the queue is empty, there is nothing to save").await;
+        return;
+    }
+
+    // Uploaded modules would stop loading once their links expire
+    let total = entries.len();
+    let entries: Vec<PlaylistEntry> = entries.into_iter()
+        .filter(|entry| !is_discord_attachment(&entry.url))
+        .collect();
+    let skipped = total - entries.len();
+    if entries.is_empty() {
+        respond_command(&ctx, interaction, "Uploaded modules can't be saved in playlists because their links expire, only modules played from a URL can").await;
+        return;
+    }
+    let skipped_note = if skipped > 0 {
+        format!(" ({skipped} uploaded modules left out, their links expire)")
+    } else {
+        String::new()
+    };
+
+    let escaped_name = escape_markdown(name);
+    let count = entries.len();
+    match database.save_playlist(guild_id, name, entries).await {
+        Ok(replaced) => {
+            let action = if replaced { "Updated" } else { "Saved" };
+            respond_command(&ctx, interaction, &format!("{action} playlist **{escaped_name}** with {count} modules{skipped_note}")).await;
+        },
+        Err(err) => {
+            log::error!("Unable to save a playlist: {err}");
+            respond_command(&ctx, interaction, "Unable to save the playlist").await;
+        },
+    }
+}
+
+pub async fn handle_play(ctx: Context, interaction: &CommandInteraction, options: &Vec<ResolvedOption<'_>>) {
+    let ResolvedValue::String(name) = options[0].value else { unreachable!() };
+
+    let database = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .database.clone();
+    let entries = match database.get_playlist(interaction.guild_id.unwrap(), name).await {
+        Ok(Some(entries)) => entries,
+        Ok(None) => {
+            respond_command(&ctx, interaction, &format!("There is no playlist named **{}**", escape_markdown(name))).await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Unable to load a playlist: {err}");
+            respond_command(&ctx, interaction, "Unable to load the playlist").await;
+            return;
+        },
+    };
+
+    if !join_and_defer(&ctx, interaction).await {
+        return;
+    }
+
+    let entries = entries.into_iter()
+        .map(|entry| (entry.url, Some(entry.filehash)))
+        .collect();
+    enqueue_entries(&ctx, interaction, entries).await;
+}
+
+pub async fn handle_list(ctx: Context, interaction: &CommandInteraction) {
+    let database = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .database.clone();
+    let playlists = match database.list_playlists(interaction.guild_id.unwrap()).await {
+        Ok(playlists) => playlists,
+        Err(err) => {
+            log::error!("Unable to list playlists: {err}");
+            respond_command(&ctx, interaction, "Unable to list playlists").await;
+            return;
+        },
+    };
+
+    if playlists.is_empty() {
+        respond_command(&ctx, interaction, "This server has no saved playlists. Use /playlist save to make one.").await;
+        return;
+    }
+
+    let mut content = String::from("Saved playlists:\n");
+    for (i, (name, count)) in playlists.iter().enumerate() {
+        let line = format!("**{}** ({count} modules)\n", escape_markdown(name));
+        if content.len() + line.len() > SUMMARY_MAX_LENGTH {
+            content.push_str(&format!("...and {} more", playlists.len() - i));
+            break;
+        }
+        content.push_str(&line);
+    }
+
+    respond_command(&ctx, interaction, &content).await;
+}
+
+pub async fn handle_delete(ctx: Context, interaction: &CommandInteraction, options: &Vec<ResolvedOption<'_>>) {
+    let ResolvedValue::String(name) = options[0].value else { unreachable!() };
+
+    let database = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .database.clone();
+    let escaped_name = escape_markdown(name);
+    match database.delete_playlist(interaction.guild_id.unwrap(), name).await {
+        Ok(true) => respond_command(&ctx, interaction, &format!("Deleted playlist **{escaped_name}**")).await,
+        Ok(false) => respond_command(&ctx, interaction, &format!("There is no playlist named **{escaped_name}**")).await,
+        Err(err) => {
+            log::error!("Unable to delete a playlist: {err}");
+            respond_command(&ctx, interaction, "Unable to delete the playlist").await;
+        },
+    }
+}
+
+/// Joins the user's voice channel if there is no session yet, and defers the interaction.
+/// Returns false if the interaction has already been responded to with an error.
+async fn join_and_defer(ctx: &Context, interaction: &CommandInteraction) -> bool {
+    let session_exists = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .sessions.contains_key(&interaction.guild_id.unwrap());
+
+    if !session_exists {
+        let Some(connect_to) = user_voice_channel(ctx, interaction) else {
+            let response = CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                .content("Join a voice channel first".to_string())
+            );
+            interaction.create_response(&ctx.http, response).await.unwrap();
+            return false;
+        };
+
+        interaction.defer(ctx).await.unwrap();
+        if let Err(err) = initiate_session(ctx, interaction.guild_id.unwrap(), connect_to, interaction.channel_id).await {
+            followup_command(ctx, interaction, &err.to_string()).await;
+            return false;
+        }
+    } else {
+        interaction.defer(ctx).await.unwrap();
+    }
+
+    true
+}
+
+/// Downloads the playlist entries and puts them into the queue, then reports what has been queued.
+/// Each entry is a URL and optionally the hash of the file it pointed to when the playlist was saved.
+async fn enqueue_entries(ctx: &Context, interaction: &CommandInteraction, mut entries: Vec<(String, Option<String>)>) {
+    let truncated = entries.len() > PLAYLIST_MAX_ENTRIES;
+    entries.truncate(PLAYLIST_MAX_ENTRIES);
+    let total = entries.len();
+
+    let client = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .downloader_client.clone();
+
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap())
+        .cloned();
+    drop(data_lock);
+    let Some(session) = session_u else {
+        followup_command(ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    };
+
+    let requester = interaction.user.id;
+    // Download the entries concurrently, but keep the playlist order
+    let mut results = futures::stream::iter(entries)
+        .map(|(url, filehash)| {
+            let client = client.clone();
+            async move {
+                let result = match download_file(&client, &url).await {
+                    // Decoding is CPU bound, keep it off the async workers
+                    Ok(bytes) => {
+                        let url = url.clone();
+                        spawn_blocking(move || QueuedModule::probe(bytes, &url, requester)).await
+                            .unwrap_or_else(|err| Err(err.into()))
+                    },
+                    Err(err) => Err(err),
+                };
+                (url, filehash, result)
+            }
+        })
+        .buffered(PLAYLIST_WORKERS);
+
+    let mut queued = 0;
+    let mut lines: Vec<String> = Vec::with_capacity(total);
+    // Entries are queued as soon as they're ready, so that only a few of them are loaded at a time
+    while let Some((url, filehash, result)) = results.next().await {
+        match result {
+            Ok(queued_module) => {
+                let title = escape_markdown(&queued_module.title);
+                let changed = filehash.is_some_and(|filehash| filehash != queued_module.filehash);
+                let note = if changed { " (the file has changed since the playlist was saved)" } else { "" };
+                match session.data.write().await.enqueue(queued_module) {
+                    Ok(true) => { queued += 1; lines.push(format!(":arrow_forward: **{title}**{note}")) },
+                    Ok(false) => { queued += 1; lines.push(format!(":white_check_mark: **{title}**{note}")) },
+                    Err(err) => lines.push(format!(":x: **{title}**: {err}")),
+                }
+            },
+            Err(err) => lines.push(format!(":x: <{url}>: {err}")),
+        }
+    }
+    preload_next_in_queue(&session.data).await;
+
+    let mut summary = format!("Queued {queued} of {total} playlist entries");
+    if truncated {
+        summary.push_str(&format!(" (only the first {PLAYLIST_MAX_ENTRIES} entries were loaded)"));
+    }
+    summary.push('\n');
+    for (i, line) in lines.iter().enumerate() {
+        if summary.len() + line.len() > SUMMARY_MAX_LENGTH {
+            summary.push_str(&format!("...and {} more", lines.len() - i));
+            break;
+        }
+        summary.push_str(line);
+        summary.push('\n');
+    }
+
+    followup_command(ctx, interaction, &summary).await;
+}
+
+/// Parses an M3U, PLS or a plain newline separated list of URLs.
+/// Relative entries are resolved against the playlist's own URL.
+fn parse_playlist(text: &str, base_url: &Url) -> Vec<String> {
+    let lines = text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty());
+
+    let entries: Vec<&str> = if text.trim_start().to_lowercase().starts_with("[playlist]") {
+        // PLS: File1=..., File2=...
+        let mut files: Vec<(usize, &str)> = lines
+            .filter_map(|line| line.split_once('='))
+            .filter_map(|(key, value)| {
+                let index = key.trim().to_lowercase().strip_prefix("file")?.parse::<usize>().ok()?;
+                Some((index, value.trim()))
+            })
+            .collect();
+        files.sort_by_key(|(index, _)| *index);
+        files.into_iter().map(|(_, file)| file).collect()
+    } else {
+        // M3U and plain lists only differ by the comments
+        lines.filter(|line| !line.starts_with('#')).collect()
+    };
+
+    entries.into_iter()
+        .filter_map(|entry| base_url.join(entry).ok())
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(|url| url.to_string())
+        .collect()
+}
+
+pub fn register() -> CreateCommand {
+    let load_subcmd = CreateCommandOption::new(CommandOptionType::SubCommand, "load", "Enqueue all modules from an M3U, PLS or plain URL list")
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::String, "url", "Playlist file URL"))
+        .add_sub_option(CreateCommandOption::new(CommandOptionType::Attachment, "file", "Playlist file"));
+
+    let name_option = || CreateCommandOption::new(CommandOptionType::String, "name", "Playlist name")
+        .max_length(64)
+        .required(true);
+
+    CreateCommand::new("playlist").description("Manage playlists")
+        .add_option(load_subcmd)
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "save", "Save the current module and the queue as a playlist").add_sub_option(name_option()))
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "play", "Enqueue all modules from a saved playlist").add_sub_option(name_option()))
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "list", "List saved playlists of this server"))
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "delete", "Delete a saved playlist").add_sub_option(name_option()))
+}
@@ -17,9 +17,6 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::ffi::{CStr, CString};
-
-use libopenmpt_sys::{openmpt_module_get_duration_seconds, openmpt_module_get_metadata, openmpt_module_get_position_seconds};
 use serenity::all::{CommandInteraction, Context, CreateCommand};
 
 use crate::{botdata::BotDataKey, misc::{escape_markdown, format_duration, respond_command}};
@@ -37,28 +34,21 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
     let session_data_lock = session.data.read().await;
     let queue = &session_data_lock
         .module_queue;
-    let key = CString::new("title").unwrap();
     let mut current_content = String::new();
     let mut queue_content = String::from("Current song queue:\n");
 
     if let Some(current_module) = &session_data_lock.current_module {
-        let mut title: String = unsafe {CStr::from_ptr(openmpt_module_get_metadata(current_module.module.0, key.as_ptr()))}
-            .to_str().unwrap()
-            .to_string();
-        title = escape_markdown(&title);
+        let title = escape_markdown(&current_module.title());
         let mut paused = String::new();
-        if title.is_empty() {
-            title = current_module.filename.clone();
-        }
         if session_data_lock.paused {
             paused = " (paused)".to_string();
         }
 
-        let position_sec = unsafe {openmpt_module_get_position_seconds(current_module.module.0)};
+        let position_sec = current_module.module.position_seconds();
         let position = std::time::Duration::from_secs_f64(position_sec);
         let position_formatted = format_duration(position);
 
-        let duration_sec = unsafe {openmpt_module_get_duration_seconds(current_module.module.0)};
+        let duration_sec = current_module.module.duration_seconds();
         let duration = std::time::Duration::from_secs_f64(duration_sec);
         let duration_formatted = format_duration(duration);
 
@@ -69,19 +59,11 @@ pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
         queue_content = "The queue is empty. Use /play to pick a song.".to_string();
     } else {
         for (i, queued_module) in queue.iter().enumerate() {
-            let duration_sec = unsafe {openmpt_module_get_duration_seconds(queued_module.module.0)};
-            let duration = std::time::Duration::from_secs_f64(duration_sec);
+            let duration = std::time::Duration::from_secs_f64(queued_module.duration);
             let duration_formatted = format_duration(duration);
+            let title = escape_markdown(&queued_module.title);
 
-            let mut title: String = unsafe {CStr::from_ptr(openmpt_module_get_metadata(queued_module.module.0, key.as_ptr()))}
-                .to_str().unwrap()
-                .to_string();
-            title = escape_markdown(&title);
-            if title.is_empty() {
-                title = queued_module.filename.clone();
-            }
-
-            queue_content.push_str(&((i+1).to_string()+": **"+&title+"** ("+&duration_formatted+")\n"));
+            queue_content.push_str(&((i+1).to_string()+": **"+&title+"** ("+&duration_formatted+", "+&queued_module.format+")\n"));
         }
     }
     drop(session_data_lock);
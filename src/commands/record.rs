@@ -0,0 +1,182 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{env, fs, path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, CreateMessage, ResolvedOption, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::database::RECORDING_SETTING;
+use crate::encoder::AudioFormat;
+use crate::misc::{can_manage_guild, escape_markdown, followup_command, respond_command};
+use crate::recorder::Recorder;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    if let ResolvedValue::SubCommand(sub_options) = &interaction.data.options()[0].value {
+        match interaction.data.options()[0].name {
+            "start" => handle_start(ctx, interaction, sub_options).await,
+            "stop" => handle_stop(ctx, interaction).await,
+            "allow" => handle_allow(ctx, interaction, sub_options).await,
+            &_ => respond_command(&ctx, interaction, "Something has gone horribly wrong").await,
+        }
+    }
+}
+
+pub async fn handle_start(ctx: Context, interaction: &CommandInteraction, options: &[ResolvedOption<'_>]) {
+    let guild_id = interaction.guild_id.unwrap();
+    let format = options.iter()
+        .find_map(|option| match (option.name, &option.value) {
+            ("format", ResolvedValue::String(name)) => AudioFormat::from_name(name),
+            _ => None,
+        })
+        .unwrap_or(AudioFormat::Opus);
+
+    let data_lock = ctx.data.read().await;
+    let botdata = data_lock.get::<BotDataKey>().unwrap();
+    let database = botdata.database.clone();
+    let session_u = botdata.sessions.get(&guild_id).cloned();
+    drop(data_lock);
+
+    match database.get_guild_flag(guild_id, RECORDING_SETTING).await {
+        Ok(true) => {},
+        Ok(false) => {
+            respond_command(&ctx, interaction, "Recording is disabled on this server. Someone with the Manage Server permission can enable it with /record allow.").await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Unable to read guild settings: {err}");
+            respond_command(&ctx, interaction, "Unable to read the settings of this server").await;
+            return;
+        },
+    }
+
+    let Some(session) = session_u else {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    };
+    let mut session_lock = session.data.write().await;
+    if session_lock.recorder.is_some() {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This session is already being recorded").await;
+        return;
+    }
+
+    // Recordings stay on the bot's host, in MODULO_RECORDINGS_DIR
+    let directory = PathBuf::from(env::var("MODULO_RECORDINGS_DIR").unwrap_or("recordings".to_owned()));
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let path = directory.join(format!("{guild_id}-{timestamp}.{}", format.extension()));
+    let recorder = fs::create_dir_all(&directory)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| Recorder::start(path, format));
+    let recorder = match recorder {
+        Ok(recorder) => recorder,
+        Err(err) => {
+            drop(session_lock);
+            log::error!("Unable to start recording: {err}");
+            respond_command(&ctx, interaction, "Unable to start recording").await;
+            return;
+        },
+    };
+    let filename = recorder.path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    session_lock.recorder = Some(recorder);
+    let text_channel_id = session_lock.text_channel_id;
+    drop(session_lock);
+
+    let content = format!("🔴 Recording started by {}, the session's output is being saved as `{filename}`", escape_markdown(&interaction.user.name));
+    respond_command(&ctx, interaction, &content).await;
+    // Listeners should know, even if the command was used somewhere else
+    if interaction.channel_id != text_channel_id {
+        let _ = text_channel_id.send_message(&ctx, CreateMessage::new().content(content)).await;
+    }
+}
+
+pub async fn handle_stop(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap()).cloned();
+    drop(data_lock);
+
+    let Some(session) = session_u else {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    };
+    let mut session_lock = session.data.write().await;
+    let Some(recorder) = session_lock.recorder.take() else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This session is not being recorded").await;
+        return;
+    };
+    let text_channel_id = session_lock.text_channel_id;
+    drop(session_lock);
+
+    // The writer has to finish the file first
+    interaction.defer(&ctx.http).await.unwrap();
+    let content = match tokio::task::spawn_blocking(move || recorder.stop()).await {
+        Ok(Ok(recording)) => format!("⏹️ Recording stopped, {}", recording.describe()),
+        Ok(Err(err)) => {
+            log::error!("Recording failed: {err}");
+            "⏹️ Recording failed, the file may be incomplete".to_owned()
+        },
+        Err(err) => {
+            log::error!("Recording task failed: {err}");
+            "⏹️ Recording failed, the file may be incomplete".to_owned()
+        },
+    };
+    followup_command(&ctx, interaction, &content).await;
+    if interaction.channel_id != text_channel_id {
+        let _ = text_channel_id.send_message(&ctx, CreateMessage::new().content(content)).await;
+    }
+}
+
+pub async fn handle_allow(ctx: Context, interaction: &CommandInteraction, options: &[ResolvedOption<'_>]) {
+    let ResolvedValue::Boolean(enabled) = options[0].value else { unreachable!() };
+    if !can_manage_guild(interaction) {
+        respond_command(&ctx, interaction, "You need the Manage Server permission to change this setting").await;
+        return;
+    }
+
+    let database = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .database.clone();
+    if let Err(err) = database.set_guild_flag(interaction.guild_id.unwrap(), RECORDING_SETTING, enabled).await {
+        log::error!("Unable to save guild settings: {err}");
+        respond_command(&ctx, interaction, "Unable to save the setting").await;
+        return;
+    }
+
+    let state = if enabled { "enabled" } else { "disabled" };
+    respond_command(&ctx, interaction, &format!("Recording is now **{state}** on this server")).await;
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("record").description("Record the session's output to a file on the bot's host")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "start", "Start recording")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::String, "format", "Audio format, Opus by default")
+                        .add_string_choice("Opus", "opus")
+                        .add_string_choice("FLAC", "flac")
+                )
+        )
+        .add_option(CreateCommandOption::new(CommandOptionType::SubCommand, "stop", "Stop recording"))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "allow", "Allow everyone on this server to record sessions")
+                .add_sub_option(CreateCommandOption::new(CommandOptionType::Boolean, "enabled", "Whether recording is allowed").required(true))
+        )
+}
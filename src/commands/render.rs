@@ -0,0 +1,123 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::Arc;
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponseFollowup, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::encoder::AudioFormat;
+use crate::misc::{download_file, escape_markdown, filename_from_url, followup_command, respond_command, upload_limit};
+use crate::render::{render, RenderSettings, MAX_RENDER_SECONDS};
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let guild_id = interaction.guild_id.unwrap();
+    let mut format = AudioFormat::Opus;
+    let mut url: Option<String> = None;
+    let mut subsong: Option<i32> = None;
+    for option in interaction.data.options() {
+        match (option.name, option.value) {
+            ("format", ResolvedValue::String(name)) => format = AudioFormat::from_name(name).unwrap_or(AudioFormat::Opus),
+            ("url", ResolvedValue::String(value)) => url = Some(value.to_owned()),
+            ("subsong", ResolvedValue::Integer(value)) => subsong = Some(value as i32),
+            _ => {}
+        }
+    }
+
+    // Use the session's settings, if there is a session
+    let data_lock = ctx.data.read().await;
+    let botdata = data_lock.get::<BotDataKey>().unwrap();
+    let client = botdata.downloader_client.clone();
+    let session_u = botdata.sessions.get(&guild_id).cloned();
+    drop(data_lock);
+
+    let mut settings = RenderSettings::default();
+    let mut current: Option<(Arc<[u8]>, String, String, i32)> = None;
+    if let Some(session) = &session_u {
+        let session_lock = session.data.read().await;
+        settings = RenderSettings::from_session(&session_lock);
+        current = session_lock.current_module.as_ref().map(|module| {
+            (module.data.clone(), module.filename.clone(), module.title(), module.module.selected_subsong())
+        });
+    }
+
+    let (data, filename, title, subsong) = match url {
+        Some(url) => {
+            interaction.defer(&ctx.http).await.unwrap();
+            let bytes = match download_file(&client, &url).await {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    followup_command(&ctx, interaction, &err.to_string()).await;
+                    return;
+                },
+            };
+            let filename = filename_from_url(&url);
+            (Arc::from(bytes), filename.clone(), filename, subsong.unwrap_or(0))
+        },
+        None => {
+            let Some((data, filename, title, current_subsong)) = current else {
+                respond_command(&ctx, interaction, "No module is currently playing, specify a URL to render").await;
+                return;
+            };
+            interaction.defer(&ctx.http).await.unwrap();
+            (data, filename, title, subsong.unwrap_or(current_subsong))
+        },
+    };
+
+    // Rendering takes a while, keep it off the async runtime and the audio thread
+    let max_size = upload_limit(&ctx, guild_id);
+    let result = tokio::task::spawn_blocking(move || render(&data, &settings, subsong, format, max_size, MAX_RENDER_SECONDS)).await;
+    let bytes = match result {
+        Ok(Ok(bytes)) => bytes,
+        Ok(Err(err)) => {
+            followup_command(&ctx, interaction, &err.to_string()).await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Render task failed: {err}");
+            followup_command(&ctx, interaction, "Unable to render the module").await;
+            return;
+        },
+    };
+
+    let stem = filename.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&filename);
+    let stem = if stem.is_empty() { "module" } else { stem };
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(format!("Rendered **{}**", escape_markdown(&title)))
+        .add_file(CreateAttachment::bytes(bytes, format!("{stem}.{}", format.extension())));
+    if let Err(err) = interaction.create_followup(&ctx.http, followup).await {
+        log::warn!("Unable to upload the rendered module: {err}");
+        followup_command(&ctx, interaction, "Unable to upload the rendered file").await;
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("render").description("Render a module into an audio file")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "format", "Audio format, Opus by default")
+                .add_string_choice("Opus", "opus")
+                .add_string_choice("FLAC", "flac")
+                .add_string_choice("WAV", "wav")
+        )
+        .add_option(CreateCommandOption::new(CommandOptionType::String, "url", "Module file URL, the current module is rendered otherwise"))
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "subsong", "Subsong to render")
+                .min_int_value(0)
+        )
+}
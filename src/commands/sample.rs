@@ -0,0 +1,161 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponseFollowup, ResolvedOption, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::database::SAMPLE_EXPORT_SETTING;
+use crate::misc::{can_manage_guild, escape_markdown, followup_command, respond_command, safe_filename, upload_limit};
+use crate::render::render_sample;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    if let ResolvedValue::SubCommand(sub_options) = &interaction.data.options()[0].value {
+        match interaction.data.options()[0].name {
+            "export" => handle_export(ctx, interaction, sub_options).await,
+            "allow" => handle_allow(ctx, interaction, sub_options).await,
+            &_ => respond_command(&ctx, interaction, "Something has gone horribly wrong").await,
+        }
+    }
+}
+
+pub async fn handle_export(ctx: Context, interaction: &CommandInteraction, options: &[ResolvedOption<'_>]) {
+    let guild_id = interaction.guild_id.unwrap();
+    let ResolvedValue::Integer(number) = options[0].value else { unreachable!() };
+
+    let data_lock = ctx.data.read().await;
+    let botdata = data_lock.get::<BotDataKey>().unwrap();
+    let database = botdata.database.clone();
+    let session_u = botdata.sessions.get(&guild_id).cloned();
+    drop(data_lock);
+
+    match database.get_guild_flag(guild_id, SAMPLE_EXPORT_SETTING).await {
+        Ok(true) => {},
+        Ok(false) => {
+            respond_command(&ctx, interaction, "Sample export is disabled on this server. Someone with the Manage Server permission can enable it with /sample allow.").await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Unable to read guild settings: {err}");
+            respond_command(&ctx, interaction, "Unable to read the settings of this server").await;
+            return;
+        },
+    }
+
+    let Some(session) = session_u else {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    };
+    let session_lock = session.data.read().await;
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let Some(module) = current_module.module.openmpt() else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no samples").await;
+        return;
+    };
+    // play_note picks instruments when there are any, so their numbers are used instead
+    let instrument_names = module.instrument_names();
+    let (kind, names) = if instrument_names.is_empty() {
+        ("sample", module.sample_names())
+    } else {
+        ("instrument", instrument_names)
+    };
+    let data = current_module.data.clone();
+    drop(session_lock);
+
+    if number < 1 || number as usize > names.len() {
+        respond_command(&ctx, interaction, &format!("This module has {} {kind}s", names.len())).await;
+        return;
+    }
+    let index = number as usize - 1;
+    let name = names[index].trim().to_owned();
+
+    interaction.defer(&ctx.http).await.unwrap();
+    let max_size = upload_limit(&ctx, guild_id);
+    let result = tokio::task::spawn_blocking(move || render_sample(&data, index as i32, max_size)).await;
+    let wav = match result {
+        Ok(Ok(wav)) => wav,
+        Ok(Err(err)) => {
+            followup_command(&ctx, interaction, &err.to_string()).await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Sample export task failed: {err}");
+            followup_command(&ctx, interaction, "Unable to export the sample").await;
+            return;
+        },
+    };
+
+    let label = if name.is_empty() { format!("{kind} {number}") } else { format!("{kind} {number} ({name})") };
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(format!("Exported {}", escape_markdown(&label)))
+        .add_file(CreateAttachment::bytes(wav, format!("{}.wav", attachment_name(&name, number))));
+    if let Err(err) = interaction.create_followup(&ctx.http, followup).await {
+        log::warn!("Unable to upload the sample: {err}");
+        followup_command(&ctx, interaction, "Unable to upload the sample").await;
+    }
+}
+
+pub async fn handle_allow(ctx: Context, interaction: &CommandInteraction, options: &[ResolvedOption<'_>]) {
+    let ResolvedValue::Boolean(enabled) = options[0].value else { unreachable!() };
+    if !can_manage_guild(interaction) {
+        respond_command(&ctx, interaction, "You need the Manage Server permission to change this setting").await;
+        return;
+    }
+
+    let database = ctx.data.read().await
+        .get::<BotDataKey>().unwrap()
+        .database.clone();
+    if let Err(err) = database.set_guild_flag(interaction.guild_id.unwrap(), SAMPLE_EXPORT_SETTING, enabled).await {
+        log::error!("Unable to save guild settings: {err}");
+        respond_command(&ctx, interaction, "Unable to save the setting").await;
+        return;
+    }
+
+    let state = if enabled { "enabled" } else { "disabled" };
+    respond_command(&ctx, interaction, &format!("Sample export is now **{state}** on this server")).await;
+}
+
+fn attachment_name(name: &str, number: i64) -> String {
+    let sanitized = safe_filename(name);
+    if sanitized.is_empty() {
+        return format!("sample{number}");
+    }
+
+    format!("{number:02}_{sanitized}")
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("sample").description("Export samples of currently playing module")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "export", "Export a sample as WAV, or an instrument for modules with instruments")
+                .add_sub_option(
+                    CreateCommandOption::new(CommandOptionType::Integer, "number", "Sample or instrument number, as shown by /samples and /instruments")
+                        .min_int_value(1)
+                        .required(true)
+                )
+        )
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::SubCommand, "allow", "Allow everyone on this server to export samples")
+                .add_sub_option(CreateCommandOption::new(CommandOptionType::Boolean, "enabled", "Whether sample export is allowed").required(true))
+        )
+}
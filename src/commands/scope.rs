@@ -0,0 +1,101 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponseFollowup, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::misc::{escape_markdown, followup_command, respond_command, upload_limit};
+use crate::render::RenderSettings;
+use crate::scope::{scope, MAX_SCOPE_SECONDS};
+
+const DEFAULT_SCOPE_SECONDS: i64 = 10;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let guild_id = interaction.guild_id.unwrap();
+    let seconds = interaction.data.options().iter()
+        .find_map(|option| match (option.name, &option.value) {
+            ("length", ResolvedValue::Integer(value)) => Some(*value),
+            _ => None,
+        })
+        .unwrap_or(DEFAULT_SCOPE_SECONDS)
+        .clamp(1, MAX_SCOPE_SECONDS) as usize;
+
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&guild_id);
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    if current_module.module.openmpt().is_none() {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no channels").await;
+        return;
+    }
+    let data = current_module.data.clone();
+    let title = current_module.title();
+    let subsong = current_module.module.selected_subsong();
+    let position = current_module.module.position_seconds();
+    let settings = RenderSettings::from_session(&session_lock);
+    drop(session_lock);
+
+    // Every channel is rendered separately, on an instance of its own
+    interaction.defer(&ctx.http).await.unwrap();
+    let max_size = upload_limit(&ctx, guild_id);
+    let result = tokio::task::spawn_blocking(move || scope(&data, &settings, subsong, position, seconds, max_size)).await;
+    let gif = match result {
+        Ok(Ok(gif)) => gif,
+        Ok(Err(err)) => {
+            followup_command(&ctx, interaction, &err.to_string()).await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Scope task failed: {err}");
+            followup_command(&ctx, interaction, "Unable to draw the scopes").await;
+            return;
+        },
+    };
+
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(format!("Channel scopes of **{}**", escape_markdown(&title)))
+        .add_file(CreateAttachment::bytes(gif, "scope.gif"));
+    if let Err(err) = interaction.create_followup(&ctx.http, followup).await {
+        log::warn!("Unable to upload the scopes: {err}");
+        followup_command(&ctx, interaction, "Unable to upload the scopes").await;
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("scope").description("Draw per-channel oscilloscopes of currently playing module, from the current position")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "length", "Length in seconds, 10 by default")
+                .min_int_value(1)
+                .max_int_value(MAX_SCOPE_SECONDS as u64)
+        )
+}
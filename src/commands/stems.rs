@@ -0,0 +1,156 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{env, fs, path::PathBuf, time::{Duration, Instant}};
+
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateAttachment, CreateCommand, CreateCommandOption, EditInteractionResponse, ResolvedValue};
+
+use crate::botdata::BotDataKey;
+use crate::encoder::AudioFormat;
+use crate::misc::{escape_markdown, respond_command, safe_filename, upload_limit};
+use crate::render::RenderSettings;
+use crate::stems::render_stems;
+
+/// Discord rate limits edits, and nobody needs to see every channel tick by
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let guild_id = interaction.guild_id.unwrap();
+    let format = interaction.data.options().iter()
+        .find_map(|option| match (option.name, &option.value) {
+            ("format", ResolvedValue::String(name)) => AudioFormat::from_name(name),
+            _ => None,
+        })
+        .unwrap_or(AudioFormat::Flac);
+
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&guild_id);
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    if current_module.module.openmpt().is_none() {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no channels").await;
+        return;
+    }
+    let data = current_module.data.clone();
+    let title = escape_markdown(&current_module.title());
+    let stem = safe_filename(current_module.filename.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&current_module.filename));
+    let subsong = current_module.module.selected_subsong();
+    let settings = RenderSettings::from_session(&session_lock);
+    drop(session_lock);
+
+    // Stems too large to upload are kept in MODULO_STEMS_DIR, if it's set
+    let stems_dir = env::var("MODULO_STEMS_DIR").ok().map(PathBuf::from);
+    let stem = if stem.is_empty() { "module".to_owned() } else { stem };
+    let filename = format!("{stem}-stems-{}.zip", interaction.id);
+    let path = stems_dir.clone().unwrap_or_else(env::temp_dir).join(&filename);
+
+    interaction.defer(&ctx.http).await.unwrap();
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::unbounded_channel();
+    let task_path = path.clone();
+    let task = tokio::task::spawn_blocking(move || {
+        render_stems(&data, &settings, subsong, format, &task_path, |done, total| {
+            let _ = progress_tx.send((done, total));
+        })
+    });
+
+    // The channel closes once rendering is over
+    let mut last_update = Instant::now();
+    while let Some((done, total)) = progress_rx.recv().await {
+        if last_update.elapsed() < PROGRESS_INTERVAL {
+            continue;
+        }
+        last_update = Instant::now();
+        let response = EditInteractionResponse::new().content(format!("Rendering stems of **{title}**, {done} of {total} channels done"));
+        let _ = interaction.edit_response(&ctx.http, response).await;
+    }
+
+    let stems = match task.await {
+        Ok(Ok(stems)) => stems,
+        Ok(Err(err)) => {
+            let _ = fs::remove_file(&path);
+            let _ = interaction.edit_response(&ctx.http, EditInteractionResponse::new().content(err.to_string())).await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Stem render task failed: {err}");
+            let _ = fs::remove_file(&path);
+            let _ = interaction.edit_response(&ctx.http, EditInteractionResponse::new().content("Unable to render the stems")).await;
+            return;
+        },
+    };
+
+    let mut summary = format!("Stems of **{title}**, {} channels", stems.num_stems);
+    if stems.num_silent > 0 {
+        summary += &format!(" ({} silent channels left out)", stems.num_silent);
+    }
+
+    let size = fs::metadata(&path).map(|metadata| metadata.len() as usize).unwrap_or(0);
+    if size > upload_limit(&ctx, guild_id) {
+        let content = match stems_dir {
+            Some(_) => format!("{summary}. The ZIP file is too large to upload ({} MiB), it has been saved as `{filename}` on the bot's host.", size / 1024 / 1024),
+            None => {
+                let _ = fs::remove_file(&path);
+                format!("The stems are too large to upload ({} MiB), try FLAC instead of WAV", size / 1024 / 1024)
+            },
+        };
+        let _ = interaction.edit_response(&ctx.http, EditInteractionResponse::new().content(content)).await;
+        return;
+    }
+
+    let zip = fs::read(&path);
+    let _ = fs::remove_file(&path);
+    let zip = match zip {
+        Ok(zip) => zip,
+        Err(err) => {
+            log::error!("Unable to read the stems: {err}");
+            let _ = interaction.edit_response(&ctx.http, EditInteractionResponse::new().content("Unable to read the stems")).await;
+            return;
+        },
+    };
+    let response = EditInteractionResponse::new()
+        .content(summary)
+        .new_attachment(CreateAttachment::bytes(zip, format!("{stem}-stems.zip")));
+    if let Err(err) = interaction.edit_response(&ctx.http, response).await {
+        log::warn!("Unable to upload the stems: {err}");
+        let _ = interaction.edit_response(&ctx.http, EditInteractionResponse::new().content("Unable to upload the stems")).await;
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("stems").description("Render every channel of currently playing module into its own file")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "format", "Audio format, FLAC by default")
+                .add_string_choice("FLAC", "flac")
+                .add_string_choice("WAV", "wav")
+        )
+}
@@ -0,0 +1,63 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use libopenmpt_sys::{openmpt_module_set_render_param, OPENMPT_MODULE_RENDER_STEREOSEPARATION_PERCENT};
+use serenity::all::{CommandInteraction, CommandOptionType, Context, CreateCommand, CreateCommandOption, ResolvedValue};
+
+use crate::{botdata::BotDataKey, misc::respond_command};
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let separation = {
+        if let ResolvedValue::Integer(value) = interaction.data.options()[0].value {
+            value as i32
+        } else { unreachable!() }
+    };
+
+    let session = session_u.cloned().unwrap();
+    let mut session_lock = session.data.write().await;
+    session_lock.stereo_separation = separation;
+    if let Some(module) = session_lock.current_module.as_ref().and_then(|current_module| current_module.module.openmpt()) {
+        unsafe {openmpt_module_set_render_param(
+            module.0,
+            OPENMPT_MODULE_RENDER_STEREOSEPARATION_PERCENT as std::os::raw::c_int,
+            separation)
+        };
+    }
+    drop(session_lock);
+
+    respond_command(&ctx, interaction, &format!("Stereo separation changed to **{separation}%**")).await;
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("stereo").description("Change stereo separation for this session")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::Integer, "separation", "0% is mono, 100% is the default, 200% is the widest")
+                .min_int_value(0)
+                .max_int_value(200)
+                .required(true)
+        )
+}
@@ -0,0 +1,88 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Duration;
+
+use libopenmpt_sys::{openmpt_module_get_duration_seconds, openmpt_module_get_num_subsongs, openmpt_module_select_subsong};
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+
+use crate::botdata::BotDataKey;
+use crate::decoder::OpenMptModuleSafe;
+use crate::misc::{followup_command, format_duration, respond_command};
+use crate::pagination::{followup_paginated, paginate_code_block};
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    if current_module.module.openmpt().is_none() {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no subsongs").await;
+        return;
+    }
+    let current_subsong = current_module.module.selected_subsong();
+    let data = current_module.data.clone();
+    drop(session_lock);
+
+    // Durations are per subsong, selecting them on the playing instance would disturb playback
+    interaction.defer(&ctx.http).await.unwrap();
+    let subsongs: Vec<(String, f64)> = tokio::task::spawn_blocking(move || {
+        let Some(scratch) = OpenMptModuleSafe::from_memory(&data) else { return Vec::new() };
+        let num_subsongs = unsafe { openmpt_module_get_num_subsongs(scratch.0) };
+        (0..num_subsongs)
+            .map(|subsong| {
+                unsafe { openmpt_module_select_subsong(scratch.0, subsong) };
+                let duration = unsafe { openmpt_module_get_duration_seconds(scratch.0) };
+                (scratch.subsong_name(subsong), duration)
+            })
+            .collect()
+    }).await.unwrap_or_default();
+
+    if subsongs.is_empty() {
+        followup_command(&ctx, interaction, "Unable to load the subsongs").await;
+        return;
+    }
+
+    let lines: Vec<String> = subsongs.iter().enumerate()
+        .map(|(subsong, (name, duration))| {
+            let marker = if subsong as i32 == current_subsong { '>' } else { ' ' };
+            format!("{marker}{subsong:>3}  {:>6}  {name}", format_duration(Duration::from_secs_f64(*duration)))
+        })
+        .collect();
+    let pages = paginate_code_block(&format!("## Subsongs ({})", lines.len()), &lines);
+    followup_paginated(&ctx, interaction, pages).await;
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("subsongs").description("List subsongs of currently playing module")
+}
@@ -0,0 +1,76 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use libopenmpt_sys::{openmpt_module_get_current_channel_vu_left, openmpt_module_get_current_channel_vu_right, openmpt_module_get_current_playing_channels, openmpt_module_get_num_channels};
+use serenity::all::{CommandInteraction, Context, CreateCommand};
+
+use crate::botdata::BotDataKey;
+use crate::misc::respond_command;
+use crate::pagination::{paginate_code_block, respond_paginated};
+
+/// Width of a single side of the meter
+const BAR_WIDTH: usize = 12;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let Some(module) = current_module.module.openmpt() else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "This module has no channels").await;
+        return;
+    };
+
+    let num_channels = unsafe { openmpt_module_get_num_channels(module.0) };
+    let playing_channels = unsafe { openmpt_module_get_current_playing_channels(module.0) };
+    let lines: Vec<String> = (0..num_channels)
+        .map(|channel| {
+            let left = unsafe { openmpt_module_get_current_channel_vu_left(module.0, channel) };
+            let right = unsafe { openmpt_module_get_current_channel_vu_right(module.0, channel) };
+            format!("{:>3} {:>width$}|{:<width$}", channel+1, bar(left), bar(right), width = BAR_WIDTH)
+        })
+        .collect();
+    drop(session_lock);
+
+    let header = format!("## Channel activity\nPlaying voices: {playing_channels}");
+    respond_paginated(&ctx, interaction, paginate_code_block(&header, &lines)).await;
+}
+
+fn bar(vu: f32) -> String {
+    let length = (vu.clamp(0.0, 1.0) * BAR_WIDTH as f32).round() as usize;
+    "█".repeat(length)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("vu").description("Show which channels of currently playing module are active")
+}
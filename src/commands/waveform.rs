@@ -0,0 +1,82 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use serenity::all::{CommandInteraction, Context, CreateAttachment, CreateCommand, CreateInteractionResponseFollowup};
+
+use crate::botdata::BotDataKey;
+use crate::misc::{escape_markdown, followup_command, respond_command};
+use crate::render::RenderSettings;
+use crate::waveform::waveform;
+
+pub async fn handle(ctx: Context, interaction: &CommandInteraction) {
+    let data_lock = ctx.data.read().await;
+    let session_u = data_lock.get::<BotDataKey>().unwrap()
+        .sessions.get(&interaction.guild_id.unwrap());
+    if session_u.is_none() {
+        respond_command(&ctx, interaction, "The bot must be in a voice channel").await;
+        return;
+    }
+
+    let session = session_u.unwrap().clone();
+    drop(data_lock);
+    let session_lock = session.data.read().await;
+
+    let Some(current_module) = &session_lock.current_module else {
+        drop(session_lock);
+        respond_command(&ctx, interaction, "No module is currently playing").await;
+        return;
+    };
+    let data = current_module.data.clone();
+    let title = current_module.title();
+    let subsong = current_module.module.selected_subsong();
+    let settings = RenderSettings::from_session(&session_lock);
+    drop(session_lock);
+
+    // The whole module is rendered on a separate instance, which takes a while
+    interaction.defer(&ctx.http).await.unwrap();
+    let result = tokio::task::spawn_blocking(move || waveform(&data, &settings, subsong)).await;
+    let waveform = match result {
+        Ok(Ok(waveform)) => waveform,
+        Ok(Err(err)) => {
+            followup_command(&ctx, interaction, &err.to_string()).await;
+            return;
+        },
+        Err(err) => {
+            log::error!("Waveform task failed: {err}");
+            followup_command(&ctx, interaction, "Unable to draw the waveform").await;
+            return;
+        },
+    };
+
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(format!(
+            "Waveform of **{}**, each column is {:.2}s. Order boundaries are marked with lines.",
+            escape_markdown(&title),
+            waveform.seconds_per_column
+        ))
+        .add_file(CreateAttachment::bytes(waveform.png, "waveform.png"));
+    if let Err(err) = interaction.create_followup(&ctx.http, followup).await {
+        log::warn!("Unable to upload the waveform: {err}");
+        followup_command(&ctx, interaction, "Unable to upload the waveform").await;
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("waveform").description("Draw the waveform and spectrogram of currently playing module")
+}
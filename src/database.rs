@@ -0,0 +1,191 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::all::GuildId;
+use tokio::task::spawn_blocking;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS playlists (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        PRIMARY KEY (guild_id, name)
+    );
+    CREATE TABLE IF NOT EXISTS playlist_entries (
+        guild_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        filehash TEXT NOT NULL,
+        PRIMARY KEY (guild_id, name, position),
+        FOREIGN KEY (guild_id, name) REFERENCES playlists (guild_id, name) ON DELETE CASCADE
+    );
+    CREATE TABLE IF NOT EXISTS guild_settings (
+        guild_id INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        value INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, key)
+    );
+";
+
+/// Lets anyone in the guild rip samples with /sample export, some artists object to it
+pub const SAMPLE_EXPORT_SETTING: &str = "sample_export";
+/// Lets anyone in the guild record sessions with /record
+pub const RECORDING_SETTING: &str = "recording";
+
+pub struct PlaylistEntry {
+    pub url: String,
+    pub filehash: String,
+}
+
+/// Persistent bot storage, backed by SQLite.
+/// The file location can be changed with the MODULO_DATABASE environment variable.
+/// Queries run on the blocking thread pool, so they don't hold up the async runtime.
+pub struct Database {
+    connection: Arc<Mutex<Connection>>
+}
+
+impl Database {
+    pub fn open(path: &str) -> Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(SCHEMA)?;
+
+        Ok(Self { connection: Arc::new(Mutex::new(connection)) })
+    }
+
+    async fn run<T, F>(&self, query: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        spawn_blocking(move || query(&mut connection.lock().unwrap())).await?
+    }
+
+    /// Saves a playlist, replacing an existing one with the same name.
+    /// Returns true if a playlist has been replaced.
+    pub async fn save_playlist(&self, guild_id: GuildId, name: &str, entries: Vec<PlaylistEntry>) -> Result<bool> {
+        let name = name.to_owned();
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let replaced = transaction.execute(
+                "DELETE FROM playlists WHERE guild_id = ?1 AND name = ?2",
+                params![guild_id.get() as i64, name]
+            )? != 0;
+            transaction.execute(
+                "INSERT INTO playlists (guild_id, name) VALUES (?1, ?2)",
+                params![guild_id.get() as i64, name]
+            )?;
+            for (position, entry) in entries.iter().enumerate() {
+                transaction.execute(
+                    "INSERT INTO playlist_entries (guild_id, name, position, url, filehash) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![guild_id.get() as i64, name, position as i64, entry.url, entry.filehash]
+                )?;
+            }
+            transaction.commit()?;
+
+            Ok(replaced)
+        }).await
+    }
+
+    /// Returns None if there is no playlist with such name.
+    pub async fn get_playlist(&self, guild_id: GuildId, name: &str) -> Result<Option<Vec<PlaylistEntry>>> {
+        let name = name.to_owned();
+        self.run(move |connection| {
+            let exists = connection.query_row(
+                "SELECT 1 FROM playlists WHERE guild_id = ?1 AND name = ?2",
+                params![guild_id.get() as i64, name],
+                |_| Ok(())
+            ).optional()?;
+            if exists.is_none() {
+                return Ok(None);
+            }
+
+            let mut statement = connection.prepare(
+                "SELECT url, filehash FROM playlist_entries WHERE guild_id = ?1 AND name = ?2 ORDER BY position"
+            )?;
+            let entries = statement.query_map(params![guild_id.get() as i64, name], |row| {
+                Ok(PlaylistEntry { url: row.get(0)?, filehash: row.get(1)? })
+            })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(Some(entries))
+        }).await
+    }
+
+    /// Returns names of all playlists in a guild along with their entry counts.
+    pub async fn list_playlists(&self, guild_id: GuildId) -> Result<Vec<(String, usize)>> {
+        self.run(move |connection| {
+            let mut statement = connection.prepare(
+                "SELECT playlists.name, COUNT(playlist_entries.position) FROM playlists
+                LEFT JOIN playlist_entries ON playlists.guild_id = playlist_entries.guild_id AND playlists.name = playlist_entries.name
+                WHERE playlists.guild_id = ?1
+                GROUP BY playlists.name
+                ORDER BY playlists.name"
+            )?;
+            let playlists = statement.query_map(params![guild_id.get() as i64], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize))
+            })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(playlists)
+        }).await
+    }
+
+    /// Returns false if there was no playlist with such name.
+    pub async fn delete_playlist(&self, guild_id: GuildId, name: &str) -> Result<bool> {
+        let name = name.to_owned();
+        self.run(move |connection| {
+            let deleted = connection.execute(
+                "DELETE FROM playlists WHERE guild_id = ?1 AND name = ?2",
+                params![guild_id.get() as i64, name]
+            )?;
+
+            Ok(deleted != 0)
+        }).await
+    }
+
+    /// Guild settings are off until enabled.
+    pub async fn get_guild_flag(&self, guild_id: GuildId, key: &'static str) -> Result<bool> {
+        self.run(move |connection| {
+            let value = connection.query_row(
+                "SELECT value FROM guild_settings WHERE guild_id = ?1 AND key = ?2",
+                params![guild_id.get() as i64, key],
+                |row| row.get::<_, i64>(0)
+            ).optional()?;
+
+            Ok(value.is_some_and(|value| value != 0))
+        }).await
+    }
+
+    pub async fn set_guild_flag(&self, guild_id: GuildId, key: &'static str, enabled: bool) -> Result<()> {
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT INTO guild_settings (guild_id, key, value) VALUES (?1, ?2, ?3)
+                ON CONFLICT (guild_id, key) DO UPDATE SET value = excluded.value",
+                params![guild_id.get() as i64, key, enabled as i64]
+            )?;
+
+            Ok(())
+        }).await
+    }
+}
@@ -0,0 +1,264 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Plain audio files (WAV, FLAC, OGG, MP3), decoded with symphonia.
+// Tracker artists often share rendered versions of their tracks.
+
+use std::{collections::HashMap, io::Cursor, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use symphonia::core::{audio::SampleBuffer, codecs::{DecoderOptions, CODEC_TYPE_NULL}, errors::Error as SymphoniaError, formats::{FormatOptions, FormatReader}, io::MediaSourceStream, meta::{MetadataOptions, MetadataRevision, StandardTagKey}, probe::Hint};
+
+use super::Decoder;
+
+/// Consumed frames are dropped from the buffer once there are this many of them
+const DRAIN_THRESHOLD: usize = 8192;
+/// How many packets the bitrate is averaged over when the length has to be estimated
+const ESTIMATE_PACKETS: usize = 256;
+
+pub struct AudioDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn symphonia::core::codecs::Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    /// Total amount of frames, estimated if the container doesn't know it
+    num_frames: Option<u64>,
+    metadata: HashMap<&'static str, String>,
+
+    /// Reused for every packet, sized for the largest one so far
+    sample_buffer: Option<SampleBuffer<f32>>,
+
+    /// Decoded stereo frames at the file's own sample rate
+    frames: Vec<[f32; 2]>,
+    /// Frames output so far, at `output_rate`. The position in the file is worked out from it
+    /// instead of being accumulated, so that rounding errors can't add frames at the end.
+    frames_output: u64,
+    output_rate: u64,
+    /// Frames dropped from the start of `frames` so far
+    frames_drained: u64,
+    ended: bool,
+}
+
+impl AudioDecoder {
+    pub fn new(bytes: &Arc<[u8]>) -> Result<Self> {
+        let source = MediaSourceStream::new(Box::new(Cursor::new(bytes.clone())), Default::default());
+        let mut probed = symphonia::default::get_probe()
+            .format(&Hint::new(), source, &FormatOptions::default(), &MetadataOptions::default())
+            .map_err(|_| anyhow!("Unsupported audio format"))?;
+
+        let track = probed.format.tracks().iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(anyhow!("The file has no audio tracks"))?;
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|_| anyhow!("Unsupported audio codec"))?;
+        let sample_rate = track.codec_params.sample_rate
+            .ok_or(anyhow!("Unknown sample rate"))?;
+
+        let mut metadata = HashMap::new();
+        if let Some(codec) = symphonia::default::get_codecs().get_codec(track.codec_params.codec) {
+            metadata.insert("type", codec.short_name.to_owned());
+            metadata.insert("type_long", codec.long_name.to_owned());
+        }
+        // Tags may come both from the container and from ID3 tags in front of it
+        let track_id = track.id;
+        let num_frames = track.codec_params.n_frames
+            .or_else(|| estimate_num_frames(bytes, sample_rate));
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|metadata| metadata.current().cloned()) {
+            read_tags(&revision, &mut metadata);
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            read_tags(revision, &mut metadata);
+        }
+
+        Ok(Self {
+            format: probed.format,
+            decoder,
+            track_id,
+            sample_rate,
+            num_frames,
+            metadata,
+            sample_buffer: None,
+            frames: Vec::with_capacity(DRAIN_THRESHOLD * 2),
+            frames_output: 0,
+            output_rate: 0,
+            frames_drained: 0,
+            ended: false,
+        })
+    }
+
+    /// Decodes the next packet into `frames`. Returns false at the end of the file.
+    fn decode_packet(&mut self) -> bool {
+        loop {
+            let Ok(packet) = self.format.next_packet() else { return false };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            match self.decoder.decode(&packet) {
+                Ok(buffer) => {
+                    let spec = *buffer.spec();
+                    let channels = spec.channels.count().max(1);
+                    let samples = match &mut self.sample_buffer {
+                        Some(samples) if samples.capacity() >= buffer.capacity() * channels => samples,
+                        sample_buffer => sample_buffer.insert(SampleBuffer::<f32>::new(buffer.capacity() as u64, spec)),
+                    };
+                    samples.copy_interleaved_ref(buffer);
+                    for frame in samples.samples().chunks_exact(channels) {
+                        let left = frame[0];
+                        let right = *frame.get(1).unwrap_or(&left);
+                        self.frames.push([left, right]);
+                    }
+                    return true;
+                },
+                // Skip corrupted packets
+                Err(SymphoniaError::DecodeError(_)) => continue,
+                Err(_) => return false,
+            }
+        }
+    }
+}
+
+impl Decoder for AudioDecoder {
+    fn read(&mut self, sample_rate: i32, buffer: &mut [f32]) -> usize {
+        let output_rate = sample_rate as u64;
+        if output_rate != self.output_rate {
+            // Stay at the same position in the file
+            self.frames_output = self.frames_output * output_rate / self.output_rate.max(1);
+            self.output_rate = output_rate;
+        }
+        let mut frames_read = 0;
+
+        for output in buffer.chunks_exact_mut(2) {
+            let position = self.frames_output * self.sample_rate as u64;
+            let index = (position / output_rate - self.frames_drained) as usize;
+            let fraction = (position % output_rate) as f32 / output_rate as f32;
+
+            // Linear interpolation needs the frame after the current one too
+            while !self.ended && index + 1 >= self.frames.len() {
+                if !self.decode_packet() {
+                    self.ended = true;
+                }
+            }
+            let Some(current) = self.frames.get(index) else { break };
+            let next = self.frames.get(index + 1).unwrap_or(current);
+
+            output[0] = current[0] + (next[0] - current[0]) * fraction;
+            output[1] = current[1] + (next[1] - current[1]) * fraction;
+            self.frames_output += 1;
+            frames_read += 1;
+        }
+
+        let consumed = (self.frames_output * self.sample_rate as u64 / output_rate - self.frames_drained) as usize;
+        if consumed >= DRAIN_THRESHOLD {
+            let consumed = consumed.min(self.frames.len());
+            self.frames.drain(..consumed);
+            self.frames_drained += consumed as u64;
+        }
+
+        frames_read
+    }
+
+    fn metadata(&self, key: &str) -> String {
+        self.metadata.get(key).cloned().unwrap_or_default()
+    }
+
+    fn metadata_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.metadata.keys().map(|key| key.to_string()).collect();
+        keys.sort();
+
+        keys
+    }
+
+    fn duration_seconds(&self) -> f64 {
+        match self.num_frames {
+            Some(num_frames) => num_frames as f64 / self.sample_rate as f64,
+            // Only if not even the first packets could be read
+            None => 0.0,
+        }
+    }
+
+    fn position_seconds(&self) -> f64 {
+        self.frames_output as f64 / self.output_rate.max(1) as f64
+    }
+}
+
+/// Streams without a length in their header, common for MP3 and OGG, get it estimated
+/// from the file size and the average bitrate of their first packets.
+fn estimate_num_frames(bytes: &Arc<[u8]>, sample_rate: u32) -> Option<u64> {
+    let source = MediaSourceStream::new(Box::new(Cursor::new(bytes.clone())), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(&Hint::new(), source, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?.format;
+    let track = format.tracks().iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let time_base = track.codec_params.time_base;
+
+    let mut packet_bytes = 0;
+    let mut packet_duration = 0;
+    for _ in 0..ESTIMATE_PACKETS {
+        let Ok(packet) = format.next_packet() else { break };
+        if packet.track_id() == track_id {
+            packet_bytes += packet.data.len() as u64;
+            packet_duration += packet.dur;
+        }
+    }
+    if packet_bytes == 0 || packet_duration == 0 {
+        return None;
+    }
+
+    let packet_seconds = match time_base {
+        Some(time_base) => {
+            let time = time_base.calc_time(packet_duration);
+            time.seconds as f64 + time.frac
+        },
+        None => packet_duration as f64 / sample_rate as f64,
+    };
+    // Cover art in ID3 tags would otherwise count as audio
+    let audio_bytes = bytes.len().saturating_sub(id3v2_size(bytes)) as f64;
+
+    Some((audio_bytes / packet_bytes as f64 * packet_seconds * sample_rate as f64) as u64)
+}
+
+/// Size of the ID3v2 tag at the start of the file, 0 if there is none
+fn id3v2_size(bytes: &[u8]) -> usize {
+    match bytes {
+        [b'I', b'D', b'3', _, _, flags, size @ ..] if size.len() >= 4 => {
+            // Sizes are "syncsafe", 7 bits per byte
+            let size = size[..4].iter().fold(0, |size, byte| (size << 7) | (*byte as usize & 0x7F));
+            // Plus the header, and the footer if there is one
+            size + if flags & 0x10 != 0 { 20 } else { 10 }
+        },
+        _ => 0,
+    }
+}
+
+fn read_tags(revision: &MetadataRevision, metadata: &mut HashMap<&'static str, String>) {
+    for tag in revision.tags() {
+        let key = match tag.std_key {
+            Some(StandardTagKey::TrackTitle) => "title",
+            Some(StandardTagKey::Artist) => "artist",
+            Some(StandardTagKey::Date) => "date",
+            Some(StandardTagKey::Comment) => "message",
+            _ => continue,
+        };
+        metadata.insert(key, tag.value.to_string());
+    }
+}
@@ -0,0 +1,152 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod audio;
+mod openmpt;
+mod openmpt_ext;
+mod ym;
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+pub use openmpt::OpenMptModuleSafe;
+pub use openmpt_ext::OpenMptModuleExt;
+
+/// Something that can turn a music file into PCM.
+/// libopenmpt handles tracker modules, other formats get their own decoders.
+pub trait Decoder: Send + Sync {
+    /// Renders interleaved stereo samples into the buffer.
+    /// Returns the amount of frames rendered, less than requested means the end of the song.
+    fn read(&mut self, sample_rate: i32, buffer: &mut [f32]) -> usize;
+
+    /// Returns a metadata value, or an empty string if there is no such key.
+    /// Keys follow libopenmpt's naming, e.g. "title", "artist", "type_long".
+    fn metadata(&self, key: &str) -> String;
+
+    /// Keys which can be passed to `metadata`
+    fn metadata_keys(&self) -> Vec<String>;
+
+    fn duration_seconds(&self) -> f64;
+
+    fn position_seconds(&self) -> f64;
+
+    fn num_subsongs(&self) -> i32 {
+        1
+    }
+
+    fn selected_subsong(&self) -> i32 {
+        0
+    }
+
+    /// Returns false if the subsong is out of range.
+    fn select_subsong(&mut self, subsong: i32) -> bool {
+        subsong == 0
+    }
+
+    /// Tracker specific features (patterns, samples, render settings and so on)
+    /// are only available for libopenmpt modules.
+    fn openmpt(&self) -> Option<&OpenMptModuleSafe> {
+        None
+    }
+}
+
+/// File formats recognized by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Anything libopenmpt can load
+    Tracker,
+    /// Atari ST/Amstrad CPC YM register dump
+    Ym,
+    /// YM file compressed with LHA, as most YM files are distributed
+    YmCompressed,
+    /// Commodore 64 SID tune
+    Sid,
+    /// NES Sound Format
+    Nsf,
+    /// Video Game Music register log
+    Vgm,
+    /// Plain audio file (WAV, FLAC, OGG, MP3)
+    Audio,
+}
+
+impl Format {
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes {
+            [b'Y', b'M', b'2' | b'3' | b'5' | b'6', b'!' | b'b', ..] => Format::Ym,
+            // LHA is used for plenty of other things, e.g. Amiga modules
+            [_, _, b'-', b'l', b'h', _, b'-', ..] if lha_filename(bytes).is_some_and(|name| name.to_ascii_lowercase().ends_with(b".ym")) => Format::YmCompressed,
+            [b'P' | b'R', b'S', b'I', b'D', ..] => Format::Sid,
+            [b'N', b'E', b'S', b'M', 0x1A, ..] => Format::Nsf,
+            [b'V', b'g', b'm', b' ', ..] => Format::Vgm,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..]
+            | [b'f', b'L', b'a', b'C', ..]
+            | [b'O', b'g', b'g', b'S', ..]
+            | [b'I', b'D', b'3', ..] => Format::Audio,
+            _ => Format::Tracker,
+        }
+    }
+}
+
+/// Name of the first file in an LHA archive.
+/// Level 0 and 1 headers store it right in the header, level 2 headers in an extended header.
+fn lha_filename(bytes: &[u8]) -> Option<&[u8]> {
+    match *bytes.get(20)? {
+        0 | 1 => {
+            let length = *bytes.get(21)? as usize;
+            bytes.get(22..22 + length)
+        },
+        2 => {
+            // Each extended header is its type, its data and the size of the next one
+            let mut offset = 24;
+            loop {
+                let size = u16::from_le_bytes([*bytes.get(offset)?, *bytes.get(offset + 1)?]) as usize;
+                if size < 3 {
+                    return None;
+                }
+                let header = bytes.get(offset + 2..offset + 2 + size)?;
+                if header[0] == 0x01 {
+                    return Some(&header[1..size - 2]);
+                }
+                offset += size;
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Picks a decoder based on the file's magic bytes.
+pub fn open(bytes: &Arc<[u8]>) -> Result<Box<dyn Decoder>> {
+    match Format::detect(bytes) {
+        Format::Tracker => match OpenMptModuleSafe::from_memory(bytes) {
+            Some(module) => Ok(Box::new(module)),
+            // Not every audio file has a recognizable header (e.g. MP3 without ID3 tags)
+            None => audio::AudioDecoder::new(bytes)
+                .map(|decoder| Box::new(decoder) as Box<dyn Decoder>)
+                .map_err(|_| anyhow!("Failed to initialize libopenmpt module")),
+        },
+        Format::Audio => Ok(Box::new(audio::AudioDecoder::new(bytes)?)),
+        Format::Ym => Ok(Box::new(ym::YmDecoder::new(bytes)?)),
+        Format::YmCompressed => Err(anyhow!("LHA compressed YM files are not supported, please decompress the file first")),
+        // Recognized so that they don't end up with a confusing libopenmpt error
+        Format::Sid => Err(anyhow!("SID tunes can't be played")),
+        Format::Nsf => Err(anyhow!("NSF tunes can't be played")),
+        Format::Vgm => Err(anyhow!("VGM files can't be played")),
+    }
+}
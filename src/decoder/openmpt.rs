@@ -0,0 +1,163 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{ffi::{c_char, c_void, CStr, CString}, ptr::{null, null_mut}};
+
+use libopenmpt_sys::{openmpt_free_string, openmpt_module, openmpt_module_create_from_memory2, openmpt_module_destroy, openmpt_module_format_pattern_row_channel, openmpt_module_format_pattern_row_channel_command, openmpt_module_get_channel_name, openmpt_module_get_duration_seconds, openmpt_module_get_instrument_name, openmpt_module_get_metadata, openmpt_module_get_metadata_keys, openmpt_module_get_num_channels, openmpt_module_get_num_instruments, openmpt_module_get_num_samples, openmpt_module_get_num_subsongs, openmpt_module_get_pattern_name, openmpt_module_get_position_seconds, openmpt_module_get_sample_name, openmpt_module_get_selected_subsong, openmpt_module_get_subsong_name, openmpt_module_read_interleaved_float_stereo, openmpt_module_select_subsong};
+
+use crate::misc::{decode_text, openmpt_logger};
+
+use super::Decoder;
+
+// Raw FFI in Rust kinda sucks
+// To ensure safety, please use the module in ONLY one session!!!
+unsafe impl Send for OpenMptModuleSafe {}
+unsafe impl Sync for OpenMptModuleSafe {}
+pub struct OpenMptModuleSafe(pub *mut openmpt_module);
+impl Drop for OpenMptModuleSafe {
+    fn drop(&mut self) {
+        unsafe { openmpt_module_destroy(self.0); }
+    }
+}
+
+impl OpenMptModuleSafe {
+    /// Loads a module from memory, returns None if libopenmpt could not load it.
+    pub fn from_memory(bytes: &[u8]) -> Option<Self> {
+        let module = OpenMptModuleSafe(unsafe {openmpt_module_create_from_memory2(
+            bytes.as_ptr() as *const c_void,
+            bytes.len(),
+            Some(openmpt_logger),
+            null_mut(),
+            None,
+            null_mut(),
+            null_mut(),
+            null_mut(),
+            null(),
+        )});
+        if module.0.is_null() {
+            // Nothing to destroy
+            std::mem::forget(module);
+            return None;
+        }
+
+        Some(module)
+    }
+
+    /// Returns a metadata value, or an empty string if there is no such key.
+    pub fn metadata(&self, key: &str) -> String {
+        let key = CString::new(key).unwrap();
+        unsafe {
+            let value_raw = openmpt_module_get_metadata(self.0, key.as_ptr());
+            take_string(value_raw)
+        }
+    }
+
+    pub fn sample_names(&self) -> Vec<String> {
+        let num_samples = unsafe { openmpt_module_get_num_samples(self.0) };
+        (0..num_samples)
+            .map(|index| unsafe { take_string(openmpt_module_get_sample_name(self.0, index)) })
+            .collect()
+    }
+
+    /// Formats a pattern cell in tracker notation, padded to the same width for every cell.
+    pub fn format_pattern_cell(&self, pattern: i32, row: i32, channel: i32) -> String {
+        unsafe { take_string(openmpt_module_format_pattern_row_channel(self.0, pattern, row, channel, 0, 1)) }
+    }
+
+    /// Formats a single part of a pattern cell (note, instrument, effect and so on), see OPENMPT_MODULE_COMMAND_*.
+    pub fn format_pattern_command(&self, pattern: i32, row: i32, channel: i32, command: u32) -> String {
+        unsafe { take_string(openmpt_module_format_pattern_row_channel_command(self.0, pattern, row, channel, command as i32)) }
+    }
+
+    pub fn pattern_name(&self, pattern: i32) -> String {
+        unsafe { take_string(openmpt_module_get_pattern_name(self.0, pattern)) }
+    }
+
+    pub fn subsong_name(&self, subsong: i32) -> String {
+        unsafe { take_string(openmpt_module_get_subsong_name(self.0, subsong)) }
+    }
+
+    pub fn channel_names(&self) -> Vec<String> {
+        let num_channels = unsafe { openmpt_module_get_num_channels(self.0) };
+        (0..num_channels)
+            .map(|index| unsafe { take_string(openmpt_module_get_channel_name(self.0, index)) })
+            .collect()
+    }
+
+    pub fn instrument_names(&self) -> Vec<String> {
+        let num_instruments = unsafe { openmpt_module_get_num_instruments(self.0) };
+        (0..num_instruments)
+            .map(|index| unsafe { take_string(openmpt_module_get_instrument_name(self.0, index)) })
+            .collect()
+    }
+}
+
+/// Copies a string returned by libopenmpt and frees the original.
+unsafe fn take_string(raw: *const c_char) -> String {
+    if raw.is_null() {
+        return String::new();
+    }
+    let value = decode_text(CStr::from_ptr(raw).to_bytes());
+    openmpt_free_string(raw);
+
+    value
+}
+
+impl Decoder for OpenMptModuleSafe {
+    fn read(&mut self, sample_rate: i32, buffer: &mut [f32]) -> usize {
+        unsafe { openmpt_module_read_interleaved_float_stereo(self.0, sample_rate, buffer.len()/2, buffer.as_mut_ptr()) }
+    }
+
+    fn metadata(&self, key: &str) -> String {
+        OpenMptModuleSafe::metadata(self, key)
+    }
+
+    fn metadata_keys(&self) -> Vec<String> {
+        // Semicolon separated list
+        let keys = unsafe { take_string(openmpt_module_get_metadata_keys(self.0)) };
+        keys.split(';')
+            .filter(|key| !key.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn duration_seconds(&self) -> f64 {
+        unsafe { openmpt_module_get_duration_seconds(self.0) }
+    }
+
+    fn position_seconds(&self) -> f64 {
+        unsafe { openmpt_module_get_position_seconds(self.0) }
+    }
+
+    fn num_subsongs(&self) -> i32 {
+        unsafe { openmpt_module_get_num_subsongs(self.0) }
+    }
+
+    fn selected_subsong(&self) -> i32 {
+        unsafe { openmpt_module_get_selected_subsong(self.0) }
+    }
+
+    fn select_subsong(&mut self, subsong: i32) -> bool {
+        unsafe { openmpt_module_select_subsong(self.0, subsong) != 0 }
+    }
+
+    fn openmpt(&self) -> Option<&OpenMptModuleSafe> {
+        Some(self)
+    }
+}
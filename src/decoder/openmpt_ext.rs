@@ -0,0 +1,155 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// libopenmpt's extension API isn't covered by libopenmpt-sys, the few bits in use are declared here.
+// See libopenmpt_ext.h for the originals.
+
+use std::{ffi::{c_char, c_int, c_void, CString}, mem::{size_of, ManuallyDrop}, ptr::{null, null_mut}};
+
+use libopenmpt_sys::{openmpt_error_func, openmpt_log_func, openmpt_module, openmpt_module_get_num_channels, openmpt_module_initial_ctl};
+
+use crate::misc::openmpt_logger;
+
+use super::OpenMptModuleSafe;
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+struct openmpt_module_ext {
+    _private: [u8; 0],
+}
+
+/// Function table of the "interactive" interface, the order of fields matters
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Default)]
+struct openmpt_module_ext_interface_interactive {
+    set_current_speed: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    set_current_tempo: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    set_tempo_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext, f64) -> c_int>,
+    get_tempo_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext) -> f64>,
+    set_pitch_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext, f64) -> c_int>,
+    get_pitch_factor: Option<unsafe extern "C" fn(*mut openmpt_module_ext) -> f64>,
+    set_global_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext, f64) -> c_int>,
+    get_global_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext) -> f64>,
+    set_channel_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, f64) -> c_int>,
+    get_channel_volume: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> f64>,
+    set_channel_mute_status: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, c_int) -> c_int>,
+    get_channel_mute_status: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    set_instrument_mute_status: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, c_int) -> c_int>,
+    get_instrument_mute_status: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+    play_note: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32, i32, f64, f64) -> i32>,
+    stop_note: Option<unsafe extern "C" fn(*mut openmpt_module_ext, i32) -> c_int>,
+}
+
+extern "C" {
+    fn openmpt_module_ext_create_from_memory(
+        filedata: *const c_void,
+        filesize: usize,
+        logfunc: openmpt_log_func,
+        loguser: *mut c_void,
+        errfunc: openmpt_error_func,
+        erruser: *mut c_void,
+        error: *mut c_int,
+        error_message: *mut *const c_char,
+        ctls: *const openmpt_module_initial_ctl,
+    ) -> *mut openmpt_module_ext;
+    fn openmpt_module_ext_destroy(mod_ext: *mut openmpt_module_ext);
+    fn openmpt_module_ext_get_module(mod_ext: *mut openmpt_module_ext) -> *mut openmpt_module;
+    fn openmpt_module_ext_get_interface(mod_ext: *mut openmpt_module_ext, interface_id: *const c_char, interface: *mut c_void, interface_size: usize) -> c_int;
+}
+
+/// A module with the interactive extension, for muting channels.
+/// Same rules as OpenMptModuleSafe, use it in only one place at a time.
+pub struct OpenMptModuleExt {
+    ext: *mut openmpt_module_ext,
+    interactive: openmpt_module_ext_interface_interactive,
+    /// Owned by `ext`, must not be destroyed on its own
+    module: ManuallyDrop<OpenMptModuleSafe>,
+}
+
+unsafe impl Send for OpenMptModuleExt {}
+
+impl Drop for OpenMptModuleExt {
+    fn drop(&mut self) {
+        unsafe { openmpt_module_ext_destroy(self.ext); }
+    }
+}
+
+impl OpenMptModuleExt {
+    /// Returns None if libopenmpt could not load the module or doesn't provide the interactive interface.
+    pub fn from_memory(bytes: &[u8]) -> Option<Self> {
+        let ext = unsafe { openmpt_module_ext_create_from_memory(
+            bytes.as_ptr() as *const c_void,
+            bytes.len(),
+            Some(openmpt_logger),
+            null_mut(),
+            None,
+            null_mut(),
+            null_mut(),
+            null_mut(),
+            null(),
+        ) };
+        if ext.is_null() {
+            return None;
+        }
+
+        let mut interactive = openmpt_module_ext_interface_interactive::default();
+        let interface_id = CString::new("interactive").unwrap();
+        let found = unsafe { openmpt_module_ext_get_interface(
+            ext,
+            interface_id.as_ptr(),
+            &mut interactive as *mut _ as *mut c_void,
+            size_of::<openmpt_module_ext_interface_interactive>(),
+        ) };
+        if found == 0 || interactive.set_channel_mute_status.is_none() {
+            unsafe { openmpt_module_ext_destroy(ext); }
+            return None;
+        }
+
+        let module = ManuallyDrop::new(OpenMptModuleSafe(unsafe { openmpt_module_ext_get_module(ext) }));
+        Some(Self { ext, interactive, module })
+    }
+
+    pub fn module(&self) -> &OpenMptModuleSafe {
+        &self.module
+    }
+
+    pub fn module_mut(&mut self) -> &mut OpenMptModuleSafe {
+        &mut self.module
+    }
+
+    pub fn num_channels(&self) -> i32 {
+        unsafe { openmpt_module_get_num_channels(self.module.0) }
+    }
+
+    /// Plays a note of an instrument, or of a sample if the module has no instruments, on a channel of its own.
+    /// `note` counts from C-0, panning ranges from -1 to 1. Returns the channel, or None if the note couldn't be played.
+    pub fn play_note(&mut self, instrument: i32, note: i32, volume: f64, panning: f64) -> Option<i32> {
+        let play_note = self.interactive.play_note?;
+        let channel = unsafe { play_note(self.ext, instrument, note, volume, panning) };
+
+        (channel >= 0).then_some(channel)
+    }
+
+    /// Returns false if the channel is out of range.
+    pub fn set_channel_mute(&mut self, channel: i32, mute: bool) -> bool {
+        let set_channel_mute_status = self.interactive.set_channel_mute_status.unwrap();
+        unsafe { set_channel_mute_status(self.ext, channel, mute as c_int) != 0 }
+    }
+}
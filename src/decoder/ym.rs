@@ -0,0 +1,388 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// YM files are register dumps of the AY-3-8910/YM2149 sound chip, one set of registers per frame.
+// Only the plain chip is emulated, YM5/YM6 special effects (digidrums, SID voice, sync buzzer) are ignored.
+
+use anyhow::{anyhow, Result};
+
+use crate::misc::decode_text;
+
+use super::Decoder;
+
+/// Output levels of the chip's logarithmic DAC
+const VOLUME_TABLE: [f32; 16] = [
+    0.0, 0.0100, 0.0145, 0.0211, 0.0307, 0.0455, 0.0645, 0.1074,
+    0.1266, 0.2050, 0.2922, 0.3728, 0.4925, 0.6353, 0.8056, 1.0,
+];
+
+/// Atari ST clock, used by formats which don't specify one
+const DEFAULT_CLOCK: u32 = 2_000_000;
+const DEFAULT_FRAME_RATE: u32 = 50;
+
+#[derive(Default)]
+struct Tone {
+    period: u32,
+    counter: u32,
+    output: bool,
+}
+
+#[derive(Default)]
+struct Envelope {
+    period: u32,
+    counter: u32,
+    shape: u8,
+    step: u8,
+    attack: bool,
+    holding: bool,
+    volume: u8,
+}
+
+impl Envelope {
+    fn restart(&mut self, shape: u8) {
+        self.shape = shape & 0x0F;
+        self.counter = 0;
+        self.step = 0;
+        self.attack = self.shape & 0b0100 != 0;
+        self.holding = false;
+        self.volume = if self.attack { 0 } else { 15 };
+    }
+
+    fn tick(&mut self) {
+        if self.holding {
+            return;
+        }
+        self.counter += 1;
+        if self.counter < self.period.max(1) * 2 {
+            return;
+        }
+        self.counter = 0;
+
+        self.step += 1;
+        if self.step > 15 {
+            let cont = self.shape & 0b1000 != 0;
+            let alternate = self.shape & 0b0010 != 0;
+            let hold = self.shape & 0b0001 != 0;
+
+            if !cont {
+                self.holding = true;
+                self.volume = 0;
+                return;
+            }
+            if hold {
+                self.holding = true;
+                self.volume = if self.attack != alternate { 15 } else { 0 };
+                return;
+            }
+            if alternate {
+                self.attack = !self.attack;
+            }
+            self.step = 0;
+        }
+
+        self.volume = if self.attack { self.step } else { 15 - self.step };
+    }
+}
+
+struct Ay {
+    registers: [u8; 16],
+    tones: [Tone; 3],
+    noise_counter: u32,
+    noise_rng: u32,
+    envelope: Envelope,
+}
+
+impl Ay {
+    fn new() -> Self {
+        Self {
+            registers: [0; 16],
+            tones: Default::default(),
+            noise_counter: 0,
+            noise_rng: 1,
+            envelope: Envelope::default(),
+        }
+    }
+
+    fn write(&mut self, register: usize, value: u8) {
+        self.registers[register] = value;
+        match register {
+            0..=5 => {
+                let channel = register / 2;
+                self.tones[channel].period = self.registers[channel*2] as u32 | ((self.registers[channel*2 + 1] as u32 & 0x0F) << 8);
+            },
+            11 | 12 => self.envelope.period = self.registers[11] as u32 | ((self.registers[12] as u32) << 8),
+            13 => self.envelope.restart(value),
+            _ => {}
+        }
+    }
+
+    /// Advances the chip by 8 clock cycles and returns the output level of each channel.
+    fn tick(&mut self) -> [f32; 3] {
+        for tone in self.tones.iter_mut() {
+            tone.counter += 1;
+            if tone.counter >= tone.period.max(1) {
+                tone.counter = 0;
+                tone.output = !tone.output;
+            }
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= (self.registers[6] as u32 & 0x1F).max(1) * 2 {
+            self.noise_counter = 0;
+            self.noise_rng = (((self.noise_rng & 1) ^ ((self.noise_rng >> 3) & 1)) << 16) | (self.noise_rng >> 1);
+        }
+        let noise_output = self.noise_rng & 1 != 0;
+
+        self.envelope.tick();
+
+        let mixer = self.registers[7];
+        let mut levels = [0.0; 3];
+        for (channel, level) in levels.iter_mut().enumerate() {
+            let tone_enabled = mixer & (1 << channel) == 0;
+            let noise_enabled = mixer & (1 << (channel + 3)) == 0;
+            let output = (self.tones[channel].output || !tone_enabled) && (noise_output || !noise_enabled);
+            if !output {
+                continue;
+            }
+
+            let amplitude = self.registers[8 + channel];
+            let volume = if amplitude & 0x10 != 0 { self.envelope.volume } else { amplitude & 0x0F };
+            *level = VOLUME_TABLE[volume as usize];
+        }
+
+        levels
+    }
+}
+
+pub struct YmDecoder {
+    frames: Vec<[u8; 16]>,
+    frame_rate: u32,
+    clock: u32,
+    format: &'static str,
+    title: String,
+    author: String,
+    comment: String,
+
+    ay: Ay,
+    frame_index: usize,
+    samples_until_next_frame: f64,
+    ticks_pending: f64,
+    /// Previous input and output of the DC blocking filter, per stereo channel
+    dc_filter: [(f32, f32); 2],
+}
+
+impl YmDecoder {
+    pub fn new(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes, position: 4 };
+        let mut decoder = Self {
+            frames: Vec::new(),
+            frame_rate: DEFAULT_FRAME_RATE,
+            clock: DEFAULT_CLOCK,
+            format: "",
+            title: String::new(),
+            author: String::new(),
+            comment: String::new(),
+            ay: Ay::new(),
+            frame_index: 0,
+            samples_until_next_frame: 0.0,
+            ticks_pending: 0.0,
+            dc_filter: [(0.0, 0.0); 2],
+        };
+
+        match &bytes[..4] {
+            b"YM2!" | b"YM3!" | b"YM3b" => {
+                decoder.format = if &bytes[..4] == b"YM2!" { "YM2" } else { "YM3" };
+                let mut data = &bytes[4..];
+                if &bytes[..4] == b"YM3b" {
+                    // Loop frame at the very end
+                    data = &data[..data.len().saturating_sub(4)];
+                }
+                decoder.frames = deinterleave(data, data.len() / 14, 14);
+            },
+            b"YM5!" | b"YM6!" => {
+                decoder.format = if &bytes[..4] == b"YM5!" { "YM5" } else { "YM6" };
+                if reader.take(8)? != b"LeOnArD!" {
+                    return Err(anyhow!("Invalid YM file signature"));
+                }
+                let num_frames = reader.u32()? as usize;
+                let attributes = reader.u32()?;
+                let num_digidrums = reader.u16()?;
+                decoder.clock = reader.u32()?;
+                decoder.frame_rate = reader.u16()? as u32;
+                let _loop_frame = reader.u32()?;
+                let extra_size = reader.u16()?;
+                reader.take(extra_size as usize)?;
+                for _ in 0..num_digidrums {
+                    let size = reader.u32()?;
+                    reader.take(size as usize)?;
+                }
+                decoder.title = reader.string()?;
+                decoder.author = reader.string()?;
+                decoder.comment = reader.string()?;
+
+                let data = reader.take(num_frames * 16)?;
+                decoder.frames = if attributes & 1 != 0 {
+                    deinterleave(data, num_frames, 16)
+                } else {
+                    data.chunks_exact(16)
+                        .map(|chunk| chunk.try_into().unwrap())
+                        .collect()
+                };
+            },
+            _ => return Err(anyhow!("Unsupported YM file version")),
+        }
+
+        if decoder.frames.is_empty() {
+            return Err(anyhow!("The YM file has no frames"));
+        }
+        if decoder.frame_rate == 0 || decoder.clock == 0 {
+            return Err(anyhow!("Invalid YM file header"));
+        }
+
+        Ok(decoder)
+    }
+
+    fn load_frame(&mut self) {
+        let frame = self.frames[self.frame_index];
+        for (register, value) in frame.iter().take(14).enumerate() {
+            // 0xFF in the envelope shape register means "don't restart the envelope"
+            if register == 13 && *value == 0xFF {
+                continue;
+            }
+            self.ay.write(register, *value);
+        }
+        self.frame_index += 1;
+    }
+}
+
+impl Decoder for YmDecoder {
+    fn read(&mut self, sample_rate: i32, buffer: &mut [f32]) -> usize {
+        let ticks_per_sample = self.clock as f64 / 8.0 / sample_rate as f64;
+        let mut frames_read = 0;
+
+        for output in buffer.chunks_exact_mut(2) {
+            if self.samples_until_next_frame <= 0.0 {
+                if self.frame_index >= self.frames.len() {
+                    break;
+                }
+                self.load_frame();
+                self.samples_until_next_frame += sample_rate as f64 / self.frame_rate as f64;
+            }
+
+            // Average the chip output over the sample period to avoid aliasing
+            self.ticks_pending += ticks_per_sample;
+            let ticks = self.ticks_pending as u32;
+            self.ticks_pending -= ticks as f64;
+            let mut sum = [0.0; 3];
+            for _ in 0..ticks {
+                let levels = self.ay.tick();
+                for channel in 0..3 {
+                    sum[channel] += levels[channel];
+                }
+            }
+            let [a, b, c] = sum.map(|level| level / ticks.max(1) as f32);
+
+            // ABC stereo, as on the Amstrad CPC and most emulators
+            let mixed = [a + b*0.5, c + b*0.5];
+            for (channel, sample) in mixed.iter().enumerate() {
+                // The chip only outputs positive voltages, remove the DC offset
+                let (previous_input, previous_output) = self.dc_filter[channel];
+                let filtered = sample - previous_input + 0.995*previous_output;
+                self.dc_filter[channel] = (*sample, filtered);
+                output[channel] = filtered * 0.5;
+            }
+
+            self.samples_until_next_frame -= 1.0;
+            frames_read += 1;
+        }
+
+        frames_read
+    }
+
+    fn metadata(&self, key: &str) -> String {
+        match key {
+            "title" => self.title.clone(),
+            "artist" => self.author.clone(),
+            "message" => self.comment.clone(),
+            "type" => self.format.to_lowercase(),
+            "type_long" => format!("{} (AY-3-8910/YM2149 register dump)", self.format),
+            _ => String::new(),
+        }
+    }
+
+    fn metadata_keys(&self) -> Vec<String> {
+        ["type", "type_long", "title", "artist", "message"].map(str::to_owned).to_vec()
+    }
+
+    fn duration_seconds(&self) -> f64 {
+        self.frames.len() as f64 / self.frame_rate as f64
+    }
+
+    fn position_seconds(&self) -> f64 {
+        self.frame_index as f64 / self.frame_rate as f64
+    }
+}
+
+/// Older YM versions and most YM5/YM6 files store all frames of register 0, then all frames of register 1 and so on.
+fn deinterleave(data: &[u8], num_frames: usize, num_registers: usize) -> Vec<[u8; 16]> {
+    let mut frames = vec![[0u8; 16]; num_frames];
+    for (i, frame) in frames.iter_mut().enumerate() {
+        for register in 0..num_registers {
+            frame[register] = data[register*num_frames + i];
+        }
+    }
+
+    frames
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position.checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(anyhow!("Unexpected end of YM file"))?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Null terminated string
+    fn string(&mut self) -> Result<String> {
+        let length = self.bytes[self.position..].iter()
+            .position(|byte| *byte == 0)
+            .ok_or(anyhow!("Unexpected end of YM file"))?;
+        let string = decode_text(self.take(length)?);
+        self.take(1)?;
+
+        Ok(string)
+    }
+}
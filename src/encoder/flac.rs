@@ -0,0 +1,453 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// A small FLAC encoder: 16-bit stereo, fixed predictors and a single Rice partition per subframe.
+// Compresses worse than libFLAC, but still well enough to make a difference for upload sizes.
+
+use anyhow::Result;
+
+use super::{to_i16, Encoder, SAMPLE_RATE};
+
+const BLOCK_SIZE: usize = 4096;
+const BITS_PER_SAMPLE: u32 = 16;
+/// Parameter 15 is reserved as an escape code
+const MAX_RICE_PARAMETER: u32 = 14;
+/// Offset of the total samples field, from the start of the file
+const TOTAL_SAMPLES_OFFSET: usize = 4 + 4 + 13;
+
+pub struct FlacEncoder {
+    data: Vec<u8>,
+    /// Samples waiting for a complete block, per channel
+    pending: [Vec<i32>; 2],
+    frame_number: u64,
+    total_samples: u64,
+    /// Bytes taken out with take_encoded
+    taken: usize,
+}
+
+impl FlacEncoder {
+    pub fn new() -> Self {
+        let mut writer = BitWriter::new();
+        writer.write_bytes(b"fLaC");
+        // STREAMINFO, the last metadata block
+        writer.write(1, 1);
+        writer.write(0, 7);
+        writer.write(34, 24);
+        writer.write(BLOCK_SIZE as u64, 16);
+        writer.write(BLOCK_SIZE as u64, 16);
+        // Minimum and maximum frame sizes are unknown
+        writer.write(0, 24);
+        writer.write(0, 24);
+        writer.write(SAMPLE_RATE as u64, 20);
+        writer.write(2 - 1, 3);
+        writer.write(BITS_PER_SAMPLE as u64 - 1, 5);
+        // Total samples, filled in by finish
+        writer.write(0, 36);
+        // MD5 of the audio is optional
+        writer.write_bytes(&[0; 16]);
+
+        Self {
+            data: writer.into_bytes(),
+            pending: [Vec::with_capacity(BLOCK_SIZE), Vec::with_capacity(BLOCK_SIZE)],
+            frame_number: 0,
+            total_samples: 0,
+            taken: 0,
+        }
+    }
+
+    fn write_frame(&mut self, block_size: usize) {
+        let mut writer = BitWriter::new();
+
+        // Frame header
+        writer.write(0b11111111111110, 14);
+        writer.write(0, 1);
+        // Fixed block size stream
+        writer.write(0, 1);
+        if block_size == BLOCK_SIZE {
+            // 256 * 2^(12-8)
+            writer.write(0b1100, 4);
+        } else {
+            // 16-bit block size at the end of the header
+            writer.write(0b0111, 4);
+        }
+        // 48 kHz
+        writer.write(0b1010, 4);
+        // Left and right, independently coded
+        writer.write(0b0001, 4);
+        // 16 bits per sample
+        writer.write(0b100, 3);
+        writer.write(0, 1);
+        writer.write_utf8_number(self.frame_number);
+        if block_size != BLOCK_SIZE {
+            writer.write(block_size as u64 - 1, 16);
+        }
+        let crc = crc8(writer.bytes());
+        writer.write(crc as u64, 8);
+
+        for channel in 0..2 {
+            let samples: Vec<i32> = self.pending[channel].drain(..block_size).collect();
+            write_subframe(&mut writer, &samples);
+        }
+
+        writer.align();
+        let crc = crc16(writer.bytes());
+        writer.write(crc as u64, 16);
+
+        self.data.extend_from_slice(&writer.into_bytes());
+        self.frame_number += 1;
+        self.total_samples += block_size as u64;
+    }
+}
+
+impl Encoder for FlacEncoder {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        for frame in samples.chunks_exact(2) {
+            self.pending[0].push(to_i16(frame[0]) as i32);
+            self.pending[1].push(to_i16(frame[1]) as i32);
+        }
+        while self.pending[0].len() >= BLOCK_SIZE {
+            self.write_frame(BLOCK_SIZE);
+        }
+
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.taken + self.data.len()
+    }
+
+    fn take_encoded(&mut self) -> Vec<u8> {
+        let data = std::mem::take(&mut self.data);
+        self.taken += data.len();
+
+        data
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<u8>> {
+        let remaining = self.pending[0].len();
+        if remaining > 0 {
+            self.write_frame(remaining);
+        }
+
+        // Zero total samples means unknown, which is what it stays at if the header is gone already
+        if self.taken > 0 {
+            return Ok(self.data);
+        }
+
+        // The field is 36 bits long and starts in the lower half of a byte
+        let total_samples = self.total_samples & 0xF_FFFF_FFFF;
+        self.data[TOTAL_SAMPLES_OFFSET] = (self.data[TOTAL_SAMPLES_OFFSET] & 0xF0) | (total_samples >> 32) as u8;
+        self.data[TOTAL_SAMPLES_OFFSET+1..TOTAL_SAMPLES_OFFSET+5].copy_from_slice(&(total_samples as u32).to_be_bytes());
+
+        Ok(self.data)
+    }
+}
+
+/// Picks the cheapest of a constant subframe and fixed predictors of order 0 to 4
+fn write_subframe(writer: &mut BitWriter, samples: &[i32]) {
+    // Subframe header starts with a zero bit, wasted bits flag comes last
+    if samples.iter().all(|sample| *sample == samples[0]) {
+        writer.write(0, 1);
+        writer.write(0b000000, 6);
+        writer.write(0, 1);
+        writer.write_signed(samples[0] as i64, BITS_PER_SAMPLE);
+        return;
+    }
+
+    let max_order = 4.min(samples.len() - 1);
+    let (order, residuals, parameter, _) = (0..=max_order)
+        .map(|order| {
+            let residuals = fixed_residuals(samples, order);
+            let (parameter, cost) = best_rice_parameter(&residuals);
+            (order, residuals, parameter, cost + order as u64 * BITS_PER_SAMPLE as u64)
+        })
+        .min_by_key(|(_, _, _, cost)| *cost)
+        .unwrap();
+
+    writer.write(0, 1);
+    writer.write(0b001000 | order as u64, 6);
+    writer.write(0, 1);
+    for sample in &samples[..order] {
+        writer.write_signed(*sample as i64, BITS_PER_SAMPLE);
+    }
+
+    // Rice coding with 4-bit parameters, a single partition
+    writer.write(0b00, 2);
+    writer.write(0, 4);
+    writer.write(parameter as u64, 4);
+    for residual in residuals {
+        let value = zigzag(residual);
+        writer.write_unary(value >> parameter);
+        writer.write(value & ((1 << parameter) - 1), parameter);
+    }
+}
+
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i64> {
+    let s = |i: usize| samples[i] as i64;
+    (order..samples.len())
+        .map(|i| match order {
+            0 => s(i),
+            1 => s(i) - s(i-1),
+            2 => s(i) - 2*s(i-1) + s(i-2),
+            3 => s(i) - 3*s(i-1) + 3*s(i-2) - s(i-3),
+            _ => s(i) - 4*s(i-1) + 6*s(i-2) - 4*s(i-3) + s(i-4),
+        })
+        .collect()
+}
+
+/// Returns the parameter and the amount of bits the residuals would take with it
+fn best_rice_parameter(residuals: &[i64]) -> (u32, u64) {
+    (0..=MAX_RICE_PARAMETER)
+        .map(|parameter| {
+            let bits: u64 = residuals.iter()
+                .map(|residual| (zigzag(*residual) >> parameter) + 1 + parameter as u64)
+                .sum();
+            (parameter, bits)
+        })
+        .min_by_key(|(_, bits)| *bits)
+        .unwrap()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for byte in bytes {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+
+    crc
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x8005 } else { crc << 1 };
+        }
+    }
+
+    crc
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    accumulator: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), accumulator: 0, bits: 0 }
+    }
+
+    /// Writes the lowest `bits` bits of the value, at most 32 at once
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits > 32 {
+            self.write(value >> 32, bits - 32);
+            self.write(value & 0xFFFF_FFFF, 32);
+            return;
+        }
+        if bits == 0 {
+            return;
+        }
+
+        self.accumulator = (self.accumulator << bits) | (value & ((1 << bits) - 1));
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            self.bytes.push((self.accumulator >> self.bits) as u8);
+        }
+        self.accumulator &= (1 << self.bits) - 1;
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64 & ((1 << bits) - 1), bits);
+    }
+
+    fn write_unary(&mut self, zeros: u64) {
+        let mut zeros = zeros;
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write(*byte as u64, 8);
+        }
+    }
+
+    /// Frame numbers are coded like UTF-8 characters
+    fn write_utf8_number(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        let mut continuation_bytes = 1;
+        while value >= 1 << (6 + 5 * continuation_bytes) {
+            continuation_bytes += 1;
+        }
+        let lead_mark = (0xFF00u64 >> (continuation_bytes + 1)) & 0xFF;
+        self.write(lead_mark | (value >> (6 * continuation_bytes)), 8);
+        for i in (0..continuation_bytes).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    /// Pads the last byte with zeros
+    fn align(&mut self) {
+        if self.bits > 0 {
+            self.write(0, 8 - self.bits);
+        }
+    }
+
+    /// Complete bytes written so far
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use symphonia::core::{audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError, io::MediaSourceStream, probe::Hint};
+
+    use super::{to_i16, BitWriter, Encoder, FlacEncoder, BLOCK_SIZE};
+
+    /// Decodes with CRC checks on, returns the total samples from STREAMINFO and the interleaved samples.
+    /// Also checks that the frame numbers count up.
+    fn decode(bytes: Vec<u8>) -> (Option<u64>, Vec<i16>) {
+        let source = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let mut format = symphonia::default::get_probe()
+            .format(&hint, source, &Default::default(), &Default::default())
+            .unwrap().format;
+        let track = format.default_track().unwrap();
+        let num_frames = track.codec_params.n_frames;
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions { verify: true })
+            .unwrap();
+
+        let mut samples = Vec::new();
+        for frame_number in 0.. {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(_)) => break,
+                Err(err) => panic!("Unable to read a frame: {err}"),
+            };
+            assert_eq!(packet.ts(), frame_number * BLOCK_SIZE as u64, "Frame {frame_number} is out of place");
+            let buffer = decoder.decode(&packet).unwrap();
+            let mut buffer_samples = SampleBuffer::<i16>::new(buffer.capacity() as u64, *buffer.spec());
+            buffer_samples.copy_interleaved_ref(buffer);
+            samples.extend_from_slice(buffer_samples.samples());
+        }
+
+        (num_frames, samples)
+    }
+
+    /// A bit of everything: a loud sine, quiet noise and full scale square waves
+    fn test_signal(frames: usize) -> Vec<f32> {
+        let mut noise: u32 = 1;
+        (0..frames)
+            .flat_map(|i| {
+                noise = noise.wrapping_mul(1664525).wrapping_add(1013904223);
+                let sine = (i as f32 * 0.03).sin() * 0.8;
+                let noise = (noise >> 16) as f32 / 65536.0 * 0.01;
+                let square = if (i / 50) % 2 == 0 { 1.0 } else { -1.0 };
+                [sine + noise, if i % 3000 < 1500 { square } else { noise }]
+            })
+            .collect()
+    }
+
+    fn expected(samples: &[f32]) -> Vec<i16> {
+        samples.iter().map(|sample| to_i16(*sample)).collect()
+    }
+
+    #[test]
+    fn frame_numbers_are_coded_like_utf8() {
+        for value in [0, 0x7F, 0x80, 0xA5, 0x7FF, 0x800, 0xBEEF, 0xFFFF, 0x10000, 0x10FFFF] {
+            let mut writer = BitWriter::new();
+            writer.write_utf8_number(value);
+            let character = char::from_u32(value as u32).unwrap();
+            assert_eq!(writer.into_bytes(), character.to_string().into_bytes(), "{value:#X}");
+        }
+    }
+
+    #[test]
+    fn round_trip_with_short_final_block() {
+        let frames = BLOCK_SIZE * 2 + 1000;
+        let samples = test_signal(frames);
+        let mut encoder: Box<dyn Encoder> = Box::new(FlacEncoder::new());
+        encoder.write(&samples).unwrap();
+
+        let (num_frames, decoded) = decode(encoder.finish().unwrap());
+        assert_eq!(num_frames, Some(frames as u64));
+        assert_eq!(decoded, expected(&samples));
+    }
+
+    #[test]
+    fn round_trip_constant_subframes() {
+        // Silence, then DC on one channel, across a full and a short block
+        let samples: Vec<f32> = (0..BLOCK_SIZE + 300)
+            .flat_map(|i| if i < BLOCK_SIZE { [0.0, 0.0] } else { [0.25, -0.5] })
+            .collect();
+        let mut encoder: Box<dyn Encoder> = Box::new(FlacEncoder::new());
+        encoder.write(&samples).unwrap();
+
+        let (_, decoded) = decode(encoder.finish().unwrap());
+        assert_eq!(decoded, expected(&samples));
+    }
+
+    #[test]
+    fn round_trip_streamed_with_take_encoded() {
+        // More than 128 frames, so that frame numbers take more than one byte
+        let frames = BLOCK_SIZE * 130 + 17;
+        let samples = test_signal(frames);
+        let mut encoder: Box<dyn Encoder> = Box::new(FlacEncoder::new());
+        let mut bytes = Vec::new();
+        // Odd chunk sizes, so that blocks don't line up with writes
+        for chunk in samples.chunks(2 * 1999) {
+            encoder.write(chunk).unwrap();
+            bytes.extend(encoder.take_encoded());
+        }
+        assert_eq!(encoder.len(), bytes.len() + encoder.take_encoded().len());
+        bytes.extend(encoder.finish().unwrap());
+
+        let (num_frames, decoded) = decode(bytes);
+        // The length stays unknown, as the header was taken out before the end
+        assert_eq!(num_frames, None);
+        assert_eq!(decoded, expected(&samples));
+    }
+}
@@ -0,0 +1,79 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod flac;
+mod opus;
+mod wav;
+
+use anyhow::Result;
+
+/// Output sample rate of all encoders, Opus only supports 48 kHz anyway
+pub const SAMPLE_RATE: u32 = 48000;
+
+/// Something that can turn interleaved stereo PCM into an audio file.
+pub trait Encoder: Send {
+    fn write(&mut self, samples: &[f32]) -> Result<()>;
+
+    /// Size of the encoded data so far, in bytes, including what has been taken
+    fn len(&self) -> usize;
+
+    /// Takes out the data encoded so far, so that long recordings don't have to stay in memory.
+    /// Header fields only known at the end, such as the length, are left as unknown once the start has been taken.
+    fn take_encoded(&mut self) -> Vec<u8>;
+
+    fn finish(self: Box<Self>) -> Result<Vec<u8>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFormat {
+    Flac,
+    Opus,
+    Wav,
+}
+
+impl AudioFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flac" => Some(AudioFormat::Flac),
+            "opus" => Some(AudioFormat::Opus),
+            "wav" => Some(AudioFormat::Wav),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Flac => "flac",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Wav => "wav",
+        }
+    }
+
+    pub fn encoder(&self) -> Result<Box<dyn Encoder>> {
+        Ok(match self {
+            AudioFormat::Flac => Box::new(flac::FlacEncoder::new()),
+            AudioFormat::Opus => Box::new(opus::OpusEncoder::new()?),
+            AudioFormat::Wav => Box::new(wav::WavEncoder::new()),
+        })
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16
+}
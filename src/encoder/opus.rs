@@ -0,0 +1,150 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use anyhow::{anyhow, Result};
+use audiopus::{coder::Encoder as RawEncoder, Application, Bitrate, Channels, SampleRate};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+
+use super::{Encoder, SAMPLE_RATE};
+
+/// 20 ms, per channel
+const FRAME_SIZE: usize = 960;
+const BITRATE: i32 = 160_000;
+/// Largest packet recommended by the Opus documentation
+const MAX_PACKET_SIZE: usize = 4000;
+const SERIAL: u32 = 0x4D4F444C;
+
+/// Opus in an Ogg container
+pub struct OpusEncoder {
+    encoder: RawEncoder,
+    writer: PacketWriter<Vec<u8>>,
+    /// Samples waiting for a complete frame
+    pending: Vec<f32>,
+    pre_skip: u64,
+    /// Samples per channel encoded so far, including the padding of the last frame
+    samples_encoded: u64,
+    /// Samples per channel passed to the encoder
+    samples_written: u64,
+    /// Bytes taken out with take_encoded
+    taken: usize,
+}
+
+impl OpusEncoder {
+    pub fn new() -> Result<Self> {
+        let mut encoder = RawEncoder::new(SampleRate::Hz48000, Channels::Stereo, Application::Audio)
+            .map_err(|err| anyhow!("Unable to create the Opus encoder: {err}"))?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(BITRATE))
+            .map_err(|err| anyhow!("Unable to set the Opus bitrate: {err}"))?;
+        let pre_skip = encoder.lookahead()
+            .map_err(|err| anyhow!("Unable to get the Opus lookahead: {err}"))? as u64;
+
+        let mut writer = PacketWriter::new(Vec::new());
+
+        let mut head = Vec::with_capacity(19);
+        head.extend_from_slice(b"OpusHead");
+        head.push(1); // Version
+        head.push(2); // Channels
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // Output gain
+        head.push(0); // Channel mapping family
+        writer.write_packet(head.into_boxed_slice(), SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = concat!("Modulo ", env!("CARGO_PKG_VERSION"));
+        let mut tags = Vec::new();
+        tags.extend_from_slice(b"OpusTags");
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&0u32.to_le_bytes()); // No comments
+        writer.write_packet(tags.into_boxed_slice(), SERIAL, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            encoder,
+            writer,
+            pending: Vec::with_capacity(FRAME_SIZE * 2),
+            pre_skip,
+            samples_encoded: 0,
+            samples_written: 0,
+            taken: 0,
+        })
+    }
+
+    fn encode_frame(&mut self, frame: &[f32], end_info: PacketWriteEndInfo) -> Result<()> {
+        let mut packet = vec![0u8; MAX_PACKET_SIZE];
+        let length = self.encoder.encode_float(frame, &mut packet)
+            .map_err(|err| anyhow!("Opus encoding error: {err}"))?;
+        packet.truncate(length);
+        self.samples_encoded += FRAME_SIZE as u64;
+
+        // The granule position is the amount of samples a decoder outputs, pre-skip included.
+        // The last one marks where the audio ends, so the padding gets cut off.
+        let granule_position = if end_info == PacketWriteEndInfo::EndStream {
+            self.pre_skip + self.samples_written
+        } else {
+            self.samples_encoded
+        };
+        self.writer.write_packet(packet.into_boxed_slice(), SERIAL, end_info, granule_position)?;
+
+        Ok(())
+    }
+}
+
+impl Encoder for OpusEncoder {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.pending.extend_from_slice(samples);
+        let complete = self.pending.len() / (FRAME_SIZE * 2) * FRAME_SIZE * 2;
+        let pending = std::mem::take(&mut self.pending);
+        for frame in pending[..complete].chunks_exact(FRAME_SIZE * 2) {
+            self.samples_written += FRAME_SIZE as u64;
+            self.encode_frame(frame, PacketWriteEndInfo::NormalPacket)?;
+        }
+        self.pending = pending[complete..].to_vec();
+
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.taken + self.writer.inner().len()
+    }
+
+    fn take_encoded(&mut self) -> Vec<u8> {
+        // Ogg has no length fields, nothing to leave unknown
+        let data = std::mem::take(self.writer.inner_mut());
+        self.taken += data.len();
+
+        data
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<u8>> {
+        // Pad the last frame with silence, the granule position tells players where the audio ends.
+        // The encoder's delay has to be flushed as well.
+        let remaining = (self.pending.len() / 2) as u64;
+        self.samples_written += remaining;
+        let mut last_frame = std::mem::take(&mut self.pending);
+        last_frame.resize(FRAME_SIZE * 2, 0.0);
+        if self.pre_skip > FRAME_SIZE as u64 - remaining {
+            self.encode_frame(&last_frame, PacketWriteEndInfo::NormalPacket)?;
+            self.encode_frame(&[0.0; FRAME_SIZE * 2], PacketWriteEndInfo::EndStream)?;
+        } else {
+            self.encode_frame(&last_frame, PacketWriteEndInfo::EndStream)?;
+        }
+
+        Ok(self.writer.into_inner())
+    }
+}
@@ -0,0 +1,93 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use anyhow::Result;
+
+use super::{to_i16, Encoder, SAMPLE_RATE};
+
+const HEADER_SIZE: usize = 44;
+
+/// 16-bit stereo PCM
+pub struct WavEncoder {
+    data: Vec<u8>,
+    /// Bytes taken out with take_encoded
+    taken: usize,
+}
+
+impl WavEncoder {
+    pub fn new() -> Self {
+        // The sizes are filled in once known, until then they're marked as unknown like in streamed WAV files
+        Self { data: header(u32::MAX), taken: 0 }
+    }
+}
+
+impl Encoder for WavEncoder {
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        self.data.reserve(samples.len() * 2);
+        for sample in samples {
+            self.data.extend_from_slice(&to_i16(*sample).to_le_bytes());
+        }
+
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.taken + self.data.len()
+    }
+
+    fn take_encoded(&mut self) -> Vec<u8> {
+        let data = std::mem::take(&mut self.data);
+        self.taken += data.len();
+
+        data
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Vec<u8>> {
+        if self.taken == 0 {
+            let data_size = (self.data.len() - HEADER_SIZE) as u32;
+            self.data[..HEADER_SIZE].copy_from_slice(&header(data_size));
+        }
+
+        Ok(self.data)
+    }
+}
+
+fn header(data_size: u32) -> Vec<u8> {
+    let channels: u16 = 2;
+    let bits_per_sample: u16 = 16;
+    let block_align = channels * bits_per_sample / 8;
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&data_size.saturating_add(HEADER_SIZE as u32 - 8).to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_size.to_le_bytes());
+
+    header
+}
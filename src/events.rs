@@ -28,6 +28,7 @@ use serenity::async_trait;
 
 use crate::botdata::BotDataKey;
 use crate::commands;
+use crate::pagination;
 use crate::vote;
 
 pub struct Handler;
@@ -48,6 +49,8 @@ impl EventHandler for Handler {
                 if let ComponentInteractionDataKind::Button = component_interaction.data.kind {
                     if component_interaction.data.custom_id.starts_with("vote") {
                         vote::handle_voting(ctx, component_interaction).await;
+                    } else if component_interaction.data.custom_id.starts_with("page") {
+                        pagination::handle_page_buttons(ctx, component_interaction).await;
                     }
                 }
             },
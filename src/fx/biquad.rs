@@ -0,0 +1,87 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::f32::consts::PI;
+
+use super::Effect;
+
+#[derive(Debug, Clone, Copy)]
+pub enum BiquadKind {
+    Peaking,
+    LowShelf,
+}
+
+/// Second order filter, coefficients from the Audio EQ Cookbook
+pub struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    /// Previous two inputs and outputs of each channel
+    state: [[f32; 4]; 2],
+}
+
+impl Biquad {
+    pub fn new(kind: BiquadKind, frequency: f32, gain_db: f32, q: f32, sample_rate: f32) -> Self {
+        let amplitude = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency.min(sample_rate * 0.49) / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let (b, a) = match kind {
+            BiquadKind::Peaking => (
+                [1.0 + alpha * amplitude, -2.0 * cos, 1.0 - alpha * amplitude],
+                [1.0 + alpha / amplitude, -2.0 * cos, 1.0 - alpha / amplitude],
+            ),
+            BiquadKind::LowShelf => {
+                let shelf = 2.0 * amplitude.sqrt() * alpha;
+                (
+                    [
+                        amplitude * ((amplitude + 1.0) - (amplitude - 1.0) * cos + shelf),
+                        2.0 * amplitude * ((amplitude - 1.0) - (amplitude + 1.0) * cos),
+                        amplitude * ((amplitude + 1.0) - (amplitude - 1.0) * cos - shelf),
+                    ],
+                    [
+                        (amplitude + 1.0) + (amplitude - 1.0) * cos + shelf,
+                        -2.0 * ((amplitude - 1.0) + (amplitude + 1.0) * cos),
+                        (amplitude + 1.0) + (amplitude - 1.0) * cos - shelf,
+                    ],
+                )
+            },
+        };
+
+        Biquad {
+            b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+            a: [a[1] / a[0], a[2] / a[0]],
+            state: [[0.0; 4]; 2],
+        }
+    }
+}
+
+impl Effect for Biquad {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            for (sample, state) in frame.iter_mut().zip(self.state.iter_mut()) {
+                let [x1, x2, y1, y2] = *state;
+                let x = *sample;
+                let y = self.b[0] * x + self.b[1] * x1 + self.b[2] * x2 - self.a[0] * y1 - self.a[1] * y2;
+                *state = [x, x1, y, y1];
+                *sample = y;
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::Effect;
+
+/// Highest level the limiter lets through, -1 dBFS
+const CEILING: f32 = 0.891;
+
+/// How long the gain takes to recover after a peak
+const RELEASE_SECONDS: f32 = 0.2;
+
+/// Peak limiter with instant attack, both channels share the gain so the stereo image stays put.
+pub struct Limiter {
+    gain: f32,
+    release: f32,
+}
+
+impl Limiter {
+    pub fn new(sample_rate: f32) -> Self {
+        Limiter {
+            gain: 1.0,
+            release: 1.0 - (-1.0 / (RELEASE_SECONDS * sample_rate)).exp(),
+        }
+    }
+}
+
+impl Effect for Limiter {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            let peak = frame[0].abs().max(frame[1].abs());
+            self.gain += (1.0 - self.gain) * self.release;
+            if peak * self.gain > CEILING {
+                self.gain = CEILING / peak;
+            }
+            frame[0] *= self.gain;
+            frame[1] *= self.gain;
+        }
+    }
+}
@@ -0,0 +1,131 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+mod biquad;
+mod limiter;
+mod reverb;
+
+use biquad::{Biquad, BiquadKind};
+use limiter::Limiter;
+use reverb::Reverb;
+
+/// Sessions always play at 48 kHz
+const SAMPLE_RATE: f32 = 48000.0;
+
+/// Most EQ bands a session can have
+pub const MAX_EQ_BANDS: usize = 4;
+
+/// Frequency the bass boost shelf starts at
+const BASS_BOOST_FREQUENCY: f32 = 150.0;
+
+/// Something that processes interleaved stereo audio in place.
+pub trait Effect: Send + Sync {
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqBand {
+    pub frequency: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbSettings {
+    /// Wet signal, 0 to 1
+    pub mix: f32,
+    /// Decay length, 0 to 1
+    pub room_size: f32,
+}
+
+/// What the effect chain of a session is made of, processed in the order of the fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FxSettings {
+    pub eq_bands: Vec<EqBand>,
+    pub bass_boost_db: f32,
+    pub reverb: Option<ReverbSettings>,
+    /// Keeps peaks below 0 dBFS, for modules mastered too loud
+    pub limiter: bool,
+}
+
+impl FxSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == FxSettings::default()
+    }
+
+    /// One line per effect, for /fx and /info
+    pub fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        for band in &self.eq_bands {
+            lines.push(format!("EQ: {} Hz, {:+.1} dB, Q {:.2}", band.frequency, band.gain_db, band.q));
+        }
+        if self.bass_boost_db != 0.0 {
+            lines.push(format!("Bass boost: {:+.1} dB", self.bass_boost_db));
+        }
+        if let Some(reverb) = &self.reverb {
+            lines.push(format!("Reverb: {:.0}% mix, {:.0}% room size", reverb.mix * 100.0, reverb.room_size * 100.0));
+        }
+        if self.limiter {
+            lines.push("Limiter".to_owned());
+        }
+        if lines.is_empty() {
+            return "None".to_owned();
+        }
+        lines.join("\n")
+    }
+}
+
+/// The effects a session's output goes through before it's sent to Discord.
+#[derive(Default)]
+pub struct FxChain {
+    settings: FxSettings,
+    effects: Vec<Box<dyn Effect>>,
+}
+
+impl FxChain {
+    pub fn settings(&self) -> &FxSettings {
+        &self.settings
+    }
+
+    /// Rebuilds the chain, effects that were already running start over
+    pub fn set(&mut self, settings: FxSettings) {
+        let mut effects: Vec<Box<dyn Effect>> = Vec::new();
+        for band in &settings.eq_bands {
+            effects.push(Box::new(Biquad::new(BiquadKind::Peaking, band.frequency, band.gain_db, band.q, SAMPLE_RATE)));
+        }
+        if settings.bass_boost_db != 0.0 {
+            effects.push(Box::new(Biquad::new(BiquadKind::LowShelf, BASS_BOOST_FREQUENCY, settings.bass_boost_db, std::f32::consts::FRAC_1_SQRT_2, SAMPLE_RATE)));
+        }
+        if let Some(reverb) = &settings.reverb {
+            effects.push(Box::new(Reverb::new(reverb.mix, reverb.room_size, SAMPLE_RATE)));
+        }
+        // Last, so that nothing before it can clip
+        if settings.limiter {
+            effects.push(Box::new(Limiter::new(SAMPLE_RATE)));
+        }
+        self.settings = settings;
+        self.effects = effects;
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for effect in &mut self.effects {
+            effect.process(samples);
+        }
+    }
+}
@@ -0,0 +1,109 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::Effect;
+
+/// Delays of Freeverb's comb and allpass filters, in samples at 44.1 kHz
+const COMB_DELAYS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_DELAYS: [usize; 4] = [556, 441, 341, 225];
+/// Added to the delays of the right channel to make the tail wider
+const STEREO_SPREAD: usize = 23;
+
+const DAMPING: f32 = 0.3;
+/// Keeps the sum of the comb filters from getting much louder than the input
+const INPUT_GAIN: f32 = 0.015;
+
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    filtered: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.filtered = output * (1.0 - DAMPING) + self.filtered * DAMPING;
+        self.buffer[self.position] = input + self.filtered * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * 0.5;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+/// Schroeder reverb, laid out like Freeverb
+pub struct Reverb {
+    mix: f32,
+    feedback: f32,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+}
+
+impl Reverb {
+    pub fn new(mix: f32, room_size: f32, sample_rate: f32) -> Self {
+        let scale = |delay: usize| ((delay as f32 * sample_rate / 44100.0) as usize).max(1);
+        let channel = |spread: usize| (
+            COMB_DELAYS.iter()
+                .map(|delay| Comb { buffer: vec![0.0; scale(delay + spread)], position: 0, filtered: 0.0 })
+                .collect::<Vec<_>>(),
+            ALLPASS_DELAYS.iter()
+                .map(|delay| Allpass { buffer: vec![0.0; scale(delay + spread)], position: 0 })
+                .collect::<Vec<_>>(),
+        );
+        let (left_combs, left_allpasses) = channel(0);
+        let (right_combs, right_allpasses) = channel(STEREO_SPREAD);
+
+        Reverb {
+            mix: mix.clamp(0.0, 1.0),
+            feedback: 0.7 + room_size.clamp(0.0, 1.0) * 0.28,
+            combs: [left_combs, right_combs],
+            allpasses: [left_allpasses, right_allpasses],
+        }
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_exact_mut(2) {
+            // Both channels get the same input, the spread makes the tails differ
+            let input = (frame[0] + frame[1]) * INPUT_GAIN;
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let mut wet: f32 = self.combs[channel].iter_mut()
+                    .map(|comb| comb.process(input, self.feedback))
+                    .sum();
+                for allpass in &mut self.allpasses[channel] {
+                    wet = allpass.process(wet);
+                }
+                *sample = *sample * (1.0 - self.mix) + wet * self.mix;
+            }
+        }
+    }
+}
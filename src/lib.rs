@@ -0,0 +1,38 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// The bot itself lives in main.rs, the library is shared with the other binaries in src/bin.
+
+pub mod events;
+mod commands;
+pub mod botdata;
+pub mod session;
+mod misc;
+mod vote;
+pub mod database;
+pub mod decoder;
+mod pagination;
+mod recorder;
+mod nowplaying;
+pub mod encoder;
+pub mod render;
+mod fx;
+mod scope;
+mod stems;
+mod waveform;
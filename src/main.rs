@@ -17,16 +17,10 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-mod events;
-mod commands;
-mod botdata;
-mod session;
-mod misc;
-mod vote;
-
 use std::env;
-use botdata::{BotData, BotDataKey};
-use events::Handler;
+use modulo::botdata::{BotData, BotDataKey};
+use modulo::database::Database;
+use modulo::events::Handler;
 use serenity::prelude::*;
 
 use songbird::SerenityInit;
@@ -53,11 +47,20 @@ async fn main() {
         | GatewayIntents::GUILD_VOICE_STATES;
 
     log::info!("Tracker bot initializing");
+    let database_path = env::var("MODULO_DATABASE").unwrap_or("modulo.db".to_owned());
+    let database = match Database::open(&database_path) {
+        Ok(database) => database,
+        Err(err) => {
+            log::error!("Unable to open the database at {database_path}: {err}");
+            std::process::exit(1);
+        },
+    };
+
     let mut client = Client::builder(&token, intents)
         .event_handler(Handler)
         .register_songbird()
         .await.expect("Err creating client");
-    client.data.write().await.insert::<BotDataKey>(BotData::default());
+    client.data.write().await.insert::<BotDataKey>(BotData::new(database));
 
     // Shut down on ctrl+C
     let shard_manager = client.shard_manager.clone();
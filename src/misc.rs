@@ -19,8 +19,9 @@
 
 use std::{ffi::{c_char, CStr}, os::raw::c_void, time::Duration};
 
-use serenity::all::{CommandInteraction, Context, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, GuildId};
+use serenity::all::{ChannelId, CommandInteraction, Context, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, GuildId, PremiumTier};
 use anyhow::{anyhow, Result};
+use oem_cp::{code_table::DECODING_TABLE_CP437, decode_string_complete_table};
 
 use crate::botdata::BotDataKey;
 
@@ -62,14 +63,113 @@ pub unsafe extern "C" fn openmpt_logger(message: *const c_char, user: *mut c_voi
 //         .role_by_name("DJ");
 // }
 
+/// Returns the voice channel the user who invoked the command is currently in.
+pub fn user_voice_channel(ctx: &Context, interaction: &CommandInteraction) -> Option<ChannelId> {
+    let guild = ctx.cache.guild(interaction.guild_id?)?;
+    let voicestate = guild.voice_states.get(&interaction.member.as_ref()?.user.id)?;
+
+    voicestate.channel_id
+}
+
+/// Guild settings can only be changed by users with the Manage Server permission.
+pub fn can_manage_guild(interaction: &CommandInteraction) -> bool {
+    interaction.member.as_ref()
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild())
+}
+
+/// Largest attachment the bot can upload in a guild, in bytes
+pub fn upload_limit(ctx: &Context, guild_id: GuildId) -> usize {
+    const MIB: usize = 1024 * 1024;
+    let premium_tier = ctx.cache.guild(guild_id).map(|guild| guild.premium_tier);
+    match premium_tier {
+        Some(PremiumTier::Tier2) => 50 * MIB,
+        Some(PremiumTier::Tier3) => 100 * MIB,
+        _ => 10 * MIB,
+    }
+}
+
+/// Replaces everything but letters, digits, dashes and underscores, for names that end up in filenames.
+/// May return an empty string.
+pub fn safe_filename(name: &str) -> String {
+    let sanitized: String = name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .take(64)
+        .collect();
+
+    sanitized.trim_matches('_').to_owned()
+}
+
+pub fn filename_from_url(url: &str) -> String {
+    let Ok(r_url) = reqwest::Url::parse(url) else { return String::new() };
+
+    // Detect ModArchive URL
+    if r_url.host_str() == Some("api.modarchive.org") {
+        if let Some(fragment) = r_url.fragment() {
+            // ModArchive URLs have their filename in the anchor/fragment area
+            return fragment.to_string();
+        }
+    }
+
+    // Take the last path segment and treat it as filename
+    r_url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Discord signs attachment URLs and they expire after about a day, so they can't be stored
+pub fn is_discord_attachment(url: &str) -> bool {
+    let Ok(r_url) = reqwest::Url::parse(url) else { return false };
+    matches!(r_url.host_str(), Some("cdn.discordapp.com" | "media.discordapp.net"))
+        && r_url.path().starts_with("/attachments/")
+}
+
+pub async fn download_file(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let response = client.get(url).send().await
+        .map_err(|err| anyhow!("HTTP request error: {err}"))?;
+    if let Err(err) = response.error_for_status_ref() {
+        return Err(anyhow!("Unable to fetch the module file: {err}"));
+    }
+
+    let bytes = response.bytes().await
+        .map_err(|err| anyhow!("HTTP request error: {err}"))?;
+
+    Ok(bytes.to_vec())
+}
+
+/// Characters which have a meaning in Discord's markdown, including masked links and mentions
+const MARKDOWN_CHARACTERS: &str = "\\*_~`|>#-[]()<";
+
+/// Makes a string safe to embed into a message, e.g. a module title.
+/// Besides markdown, this removes invisible characters which can be used to spoof text,
+/// keeps the string on a single line and defuses @everyone/@here mentions.
 pub fn escape_markdown(string: &str) -> String {
-    let mut new_string: String = String::new();
-    let characters_to_escape = "*_~[]()<>-#`\\~";
-    for c in characters_to_escape.chars() {
-        new_string = string.replace(c, &("\\".to_string()+&c.to_string()));
-    };
+    let mut escaped = String::with_capacity(string.len());
+    for c in string.chars() {
+        match c {
+            // Zero width characters and bidirectional overrides
+            '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{2066}'..='\u{2069}' | '\u{FEFF}' => {},
+            '\r' | '\n' => escaped.push(' '),
+            '@' => escaped.push_str("@\u{200B}"),
+            c if MARKDOWN_CHARACTERS.contains(c) => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
 
-    new_string
+    escaped
+}
+
+/// Decodes text found in module files.
+/// Anything that isn't valid UTF-8 is most likely CP437, the charset of DOS trackers.
+pub fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        Err(_) => decode_string_complete_table(bytes, &DECODING_TABLE_CP437),
+    }
 }
 
 pub fn format_duration(duration: Duration) -> String {
@@ -101,7 +201,7 @@ pub async fn remove_session(ctx: &Context, guild_id: GuildId) -> bool {
         return false;
     }
     let session = session_u.unwrap();
-    let session_lock = session.data.write().await;
+    let mut session_lock = session.data.write().await;
     session.call.lock().await
         .stop();
     let was_present = botdata.sessions.remove(&guild_id).is_some();
@@ -112,7 +212,75 @@ pub async fn remove_session(ctx: &Context, guild_id: GuildId) -> bool {
     if let Some(vote) = &session_lock.current_vote {
         vote.timer_death_handle.send(false).await.unwrap();
     }
+    if let Some(recorder) = session_lock.recorder.take() {
+        let ctx = ctx.clone();
+        let text_channel_id = session_lock.text_channel_id;
+        tokio::spawn(async move {
+            crate::recorder::stop_and_announce(&ctx, text_channel_id, recorder, "as the bot has left").await;
+        });
+    }
     drop(session_lock);
 
     was_present
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_markdown, is_discord_attachment};
+
+    #[test]
+    fn escapes_every_markdown_character() {
+        assert_eq!(escape_markdown("**bold** __underline__"), "\\*\\*bold\\*\\* \\_\\_underline\\_\\_");
+        assert_eq!(escape_markdown("~~strike~~ `code` ||spoiler||"), "\\~\\~strike\\~\\~ \\`code\\` \\|\\|spoiler\\|\\|");
+        assert_eq!(escape_markdown("> quote"), "\\> quote");
+        assert_eq!(escape_markdown("# heading"), "\\# heading");
+        assert_eq!(escape_markdown("- list"), "\\- list");
+    }
+
+    #[test]
+    fn escapes_backslashes() {
+        // An unescaped backslash would escape the backslash added for the asterisk
+        assert_eq!(escape_markdown("\\*"), "\\\\\\*");
+    }
+
+    #[test]
+    fn defuses_masked_links() {
+        assert_eq!(escape_markdown("[click](https://example.com)"), "\\[click\\]\\(https://example.com\\)");
+    }
+
+    #[test]
+    fn defuses_mentions() {
+        assert_eq!(escape_markdown("@everyone"), "@\u{200B}everyone");
+        assert_eq!(escape_markdown("@here"), "@\u{200B}here");
+        assert_eq!(escape_markdown("<@123456789>"), "\\<@\u{200B}123456789\\>");
+    }
+
+    #[test]
+    fn removes_invisible_characters() {
+        assert_eq!(escape_markdown("a\u{200B}b\u{FEFF}c"), "abc");
+        // Right-to-left override, used to reverse the text that follows
+        assert_eq!(escape_markdown("title\u{202E}3pm.exe"), "title3pm.exe");
+        // A zero width space must not be able to split a markdown sequence past the escaper
+        assert_eq!(escape_markdown("*\u{200B}*"), "\\*\\*");
+    }
+
+    #[test]
+    fn keeps_titles_on_one_line() {
+        assert_eq!(escape_markdown("line\n# heading\r\nnext"), "line \\# heading  next");
+    }
+
+    #[test]
+    fn leaves_plain_titles_alone() {
+        assert_eq!(escape_markdown("Space Debris"), "Space Debris");
+        assert_eq!(escape_markdown("Äöü ♫ 音楽"), "Äöü ♫ 音楽");
+        assert_eq!(escape_markdown(""), "");
+    }
+
+    #[test]
+    fn detects_expiring_attachment_urls() {
+        assert!(is_discord_attachment("https://cdn.discordapp.com/attachments/1/2/song.mod?ex=1&is=2&hm=3"));
+        assert!(is_discord_attachment("https://media.discordapp.net/attachments/1/2/song.xm"));
+        assert!(!is_discord_attachment("https://api.modarchive.org/downloads.php?moduleid=1#song.mod"));
+        assert!(!is_discord_attachment("https://cdn.discordapp.com/emojis/1.png"));
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// The "now playing" card sent for every new module, updated while the module plays.
+
+use std::{sync::Arc, time::Duration};
+
+use libopenmpt_sys::{openmpt_module_get_current_order, openmpt_module_get_current_row, openmpt_module_get_num_orders};
+use serenity::all::{ChannelId, Context, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage, Mentionable};
+use tokio::{sync::RwLock, task::JoinHandle};
+
+use crate::{misc::format_duration, pagination::truncate, session::{VoiceSessionData, WrappedModule}};
+
+const UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+const PROGRESS_BAR_LENGTH: usize = 20;
+pub const EMBED_COLOUR: u32 = 0x5865F2;
+
+pub fn now_playing_embed(module: &WrappedModule, paused: bool) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        // Embed titles are limited to 256 characters
+        .title(truncate(&module.title(), 256))
+        .colour(EMBED_COLOUR)
+        .footer(CreateEmbedFooter::new(&module.filename));
+
+    for (name, key) in [("Artist", "artist"), ("Tracker", "tracker"), ("Format", "type_long")] {
+        let value = module.module.metadata(key);
+        if !value.is_empty() {
+            embed = embed.field(name, value, true);
+        }
+    }
+
+    let position = module.module.position_seconds();
+    let duration = module.module.duration_seconds();
+    embed = embed
+        .field("Duration", format_duration(Duration::from_secs_f64(duration)), true)
+        .field("Requested by", module.requester.mention().to_string(), true);
+
+    let mut progress = format!(
+        "{} {}/{}",
+        progress_bar(position, duration),
+        format_duration(Duration::from_secs_f64(position)),
+        format_duration(Duration::from_secs_f64(duration))
+    );
+    if paused {
+        progress.push_str(" (paused)");
+    }
+    if let Some(openmpt) = module.module.openmpt() {
+        let order = unsafe { openmpt_module_get_current_order(openmpt.0) };
+        let num_orders = unsafe { openmpt_module_get_num_orders(openmpt.0) };
+        let row = unsafe { openmpt_module_get_current_row(openmpt.0) };
+        progress.push_str(&format!("\nOrder {order} of {num_orders}, row {row}"));
+    }
+
+    embed.description(progress)
+}
+
+fn progress_bar(position: f64, duration: f64) -> String {
+    let ratio = if duration > 0.0 { (position / duration).clamp(0.0, 1.0) } else { 0.0 };
+    let filled = (ratio * PROGRESS_BAR_LENGTH as f64).round() as usize;
+
+    "▰".repeat(filled) + &"▱".repeat(PROGRESS_BAR_LENGTH - filled)
+}
+
+/// Sends the card of the current module and spawns a task which keeps it updated.
+/// The task stops on its own once the module changes, abort it to stop earlier.
+pub async fn announce(ctx: &Context, channel_id: ChannelId, data: Arc<RwLock<VoiceSessionData>>) -> Option<JoinHandle<()>> {
+    let data_l = data.read().await;
+    let module = data_l.current_module.as_ref()?;
+    let filehash = module.filehash.clone();
+    let embed = now_playing_embed(module, data_l.paused);
+    drop(data_l);
+
+    let message = match channel_id.send_message(ctx, CreateMessage::new().embed(embed)).await {
+        Ok(message) => message,
+        Err(err) => {
+            log::warn!("Unable to send the now playing message: {err}");
+            return None;
+        }
+    };
+
+    let ctx = ctx.clone();
+    Some(tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(UPDATE_INTERVAL).await;
+
+            let data_l = data.read().await;
+            let Some(module) = data_l.current_module.as_ref().filter(|module| module.filehash == filehash) else { break };
+            let embed = now_playing_embed(module, data_l.paused);
+            drop(data_l);
+
+            // The message might have been deleted
+            if channel_id.edit_message(&ctx, message.id, EditMessage::new().embed(embed)).await.is_err() {
+                break;
+            }
+        }
+    }))
+}
@@ -0,0 +1,243 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use serenity::all::{ButtonStyle, CommandInteraction, ComponentInteraction, Context, CreateActionRow, CreateButton, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, MessageId};
+
+use crate::botdata::BotDataKey;
+
+/// Leaves some room for the page counter below the content
+pub const PAGE_MAX_LENGTH: usize = 1900;
+/// Pages are forgotten after this long, the buttons stop working then
+const PAGES_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Discord's embed limits
+const EMBED_TITLE_MAX_LENGTH: usize = 256;
+const EMBED_FIELD_NAME_MAX_LENGTH: usize = 256;
+const EMBED_FIELD_VALUE_MAX_LENGTH: usize = 1024;
+const EMBED_MAX_FIELDS: usize = 25;
+/// The actual limit is 6000, leave some room for the footer
+const EMBED_MAX_LENGTH: usize = 5500;
+/// Marks text cut by [truncate]
+const ELLIPSIS: &str = "…";
+
+pub enum Page {
+    Text(String),
+    Embed(CreateEmbed),
+}
+
+impl From<String> for Page {
+    fn from(text: String) -> Self {
+        Page::Text(text)
+    }
+}
+
+impl From<CreateEmbed> for Page {
+    fn from(embed: CreateEmbed) -> Self {
+        Page::Embed(embed)
+    }
+}
+
+/// Pages of a message which can be flipped through with buttons.
+/// Stored in BotData, keyed by the message ID.
+pub struct Pages {
+    pages: Vec<Page>,
+    current: usize,
+    created: Instant,
+}
+
+impl Pages {
+    /// Message content and embeds of the current page
+    fn message(&self) -> (String, Vec<CreateEmbed>) {
+        let counter = format!("Page {}/{}", self.current+1, self.pages.len());
+        let single = self.pages.len() == 1;
+        match &self.pages[self.current] {
+            Page::Text(text) if single => (text.clone(), Vec::new()),
+            Page::Text(text) => (format!("{text}\n-# {counter}"), Vec::new()),
+            Page::Embed(embed) if single => (String::new(), vec![embed.clone()]),
+            Page::Embed(embed) => (String::new(), vec![embed.clone().footer(CreateEmbedFooter::new(counter))]),
+        }
+    }
+
+    fn buttons(&self) -> Vec<CreateActionRow> {
+        if self.pages.len() == 1 {
+            return Vec::new();
+        }
+
+        vec![CreateActionRow::Buttons(vec![
+            CreateButton::new("page_prev").style(ButtonStyle::Secondary)
+                .label("◀")
+                .disabled(self.current == 0),
+            CreateButton::new("page_next").style(ButtonStyle::Secondary)
+                .label("▶")
+                .disabled(self.current+1 >= self.pages.len()),
+        ])]
+    }
+}
+
+/// Splits lines into pages, each starting with the header and having the lines in a code block.
+/// Lines which don't fit into a page on their own are cut.
+pub fn paginate_code_block(header: &str, lines: &[String]) -> Vec<String> {
+    // Header, code block fences and newlines
+    let max_body_length = PAGE_MAX_LENGTH - header.len() - 10;
+    let mut pages = Vec::new();
+    let mut body = String::new();
+
+    for line in lines {
+        let line = truncate(&escape_code_block(line), max_body_length - 1);
+        if !body.is_empty() && body.len() + line.len() + 1 > max_body_length {
+            pages.push(format!("{header}\n```\n{body}```"));
+            body.clear();
+        }
+        body.push_str(&line);
+        body.push('\n');
+    }
+    if !body.is_empty() {
+        pages.push(format!("{header}\n```\n{body}```"));
+    }
+
+    pages
+}
+
+/// Spreads (name, value, inline) fields over as many embeds as needed to stay within Discord's limits.
+/// Every embed gets the title, only the first one gets the description. Values which are too long are cut.
+pub fn paginate_embed_fields(title: &str, description: &str, fields: Vec<(String, String, bool)>) -> Vec<CreateEmbed> {
+    let title = truncate(title, EMBED_TITLE_MAX_LENGTH);
+    let new_embed = || CreateEmbed::new().title(&title).colour(crate::nowplaying::EMBED_COLOUR);
+
+    let mut embeds = Vec::new();
+    let mut embed = new_embed().description(description);
+    let mut length = title.len() + description.len();
+    let mut num_fields = 0;
+    for (name, value, inline) in fields {
+        let name = truncate(&name, EMBED_FIELD_NAME_MAX_LENGTH);
+        let value = truncate(&value, EMBED_FIELD_VALUE_MAX_LENGTH);
+
+        if num_fields == EMBED_MAX_FIELDS || length + name.len() + value.len() > EMBED_MAX_LENGTH {
+            embeds.push(std::mem::replace(&mut embed, new_embed()));
+            length = title.len();
+            num_fields = 0;
+        }
+        length += name.len() + value.len();
+        num_fields += 1;
+        embed = embed.field(name, value, inline);
+    }
+    embeds.push(embed);
+
+    embeds
+}
+
+/// Cuts the string to at most `max_length` bytes without splitting characters,
+/// ending it with an ellipsis if anything was cut
+pub fn truncate(string: &str, max_length: usize) -> String {
+    if string.len() <= max_length {
+        return string.to_owned();
+    }
+    let mut end = max_length.saturating_sub(ELLIPSIS.len());
+    while !string.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    string[..end].to_owned() + ELLIPSIS
+}
+
+/// Breaks up backticks with a zero width space so they can't close the code block
+pub fn escape_code_block(string: &str) -> String {
+    string.replace('`', "`\u{200B}")
+}
+
+/// Responds with the first page, adding buttons if there is more than one.
+pub async fn respond_paginated<P: Into<Page>>(ctx: &Context, interaction: &CommandInteraction, pages: Vec<P>) {
+    let pages = Pages { pages: pages.into_iter().map(Into::into).collect(), current: 0, created: Instant::now() };
+    if pages.pages.is_empty() {
+        return;
+    }
+
+    let (content, embeds) = pages.message();
+    let response = CreateInteractionResponseMessage::new()
+        .content(content)
+        .embeds(embeds)
+        .components(pages.buttons());
+    interaction.create_response(&ctx.http, CreateInteractionResponse::Message(response)).await.unwrap();
+    if pages.pages.len() == 1 {
+        return;
+    }
+
+    match interaction.get_response(&ctx.http).await {
+        Ok(message) => store_pages(ctx, message.id, pages).await,
+        Err(err) => log::warn!("Unable to get the paginated message: {err}"),
+    }
+}
+
+/// Same as respond_paginated, for deferred interactions.
+pub async fn followup_paginated<P: Into<Page>>(ctx: &Context, interaction: &CommandInteraction, pages: Vec<P>) {
+    let pages = Pages { pages: pages.into_iter().map(Into::into).collect(), current: 0, created: Instant::now() };
+    if pages.pages.is_empty() {
+        return;
+    }
+
+    let (content, embeds) = pages.message();
+    let followup = CreateInteractionResponseFollowup::new()
+        .content(content)
+        .embeds(embeds)
+        .components(pages.buttons());
+    let message = interaction.create_followup(&ctx.http, followup).await.unwrap();
+    if pages.pages.len() > 1 {
+        store_pages(ctx, message.id, pages).await;
+    }
+}
+
+async fn store_pages(ctx: &Context, message_id: MessageId, pages: Pages) {
+    let mut data_lock = ctx.data.write().await;
+    let paginators = &mut data_lock.get_mut::<BotDataKey>().unwrap().paginators;
+    paginators.retain(|_, pages| pages.created.elapsed() < PAGES_LIFETIME);
+    paginators.insert(message_id, pages);
+}
+
+pub async fn handle_page_buttons(ctx: Context, interaction: &ComponentInteraction) {
+    let mut data_lock = ctx.data.write().await;
+    let Some(pages) = data_lock.get_mut::<BotDataKey>().unwrap()
+        .paginators.get_mut(&interaction.message.id) else {
+        drop(data_lock);
+        let response = CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+            .content("These pages have expired, please run the command again")
+            .ephemeral(true)
+        );
+        interaction.create_response(&ctx.http, response).await.unwrap();
+        return;
+    };
+
+    match interaction.data.custom_id.as_str() {
+        "page_prev" => pages.current = pages.current.saturating_sub(1),
+        "page_next" => pages.current = (pages.current+1).min(pages.pages.len()-1),
+        _ => {}
+    }
+    let (content, embeds) = pages.message();
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+        .content(content)
+        .embeds(embeds)
+        .components(pages.buttons())
+    );
+    drop(data_lock);
+
+    interaction.create_response(&ctx.http, response).await.unwrap();
+}
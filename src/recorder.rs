@@ -0,0 +1,165 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Recordings of a session's output, for archiving listening parties and compo streams.
+// The audio thread only hands over copies of what it plays, encoding and writing happen on a thread of its own.
+
+use std::{fs::File, io::Write, path::PathBuf, sync::{mpsc::{sync_channel, Receiver, SyncSender, TryRecvError}, Mutex}, thread::{self, JoinHandle}, time::Duration};
+
+use anyhow::{anyhow, Result};
+use serenity::all::{ChannelId, Context, CreateMessage};
+
+use crate::encoder::{AudioFormat, SAMPLE_RATE};
+use crate::misc::format_duration;
+
+/// Recordings stop on their own after this long
+pub const MAX_RECORDING_SECONDS: usize = 6 * 60 * 60;
+/// Encoded data is written out in pieces of about this size
+const FLUSH_SIZE: usize = 1024 * 1024;
+/// How many chunks of audio can wait for the writer, about 5 seconds worth
+const BUFFER_CHUNKS: usize = 250;
+/// Songbird asks for 20 ms of audio at a time
+const CHUNK_SAMPLES: usize = SAMPLE_RATE as usize / 50 * 2;
+
+pub struct Recorder {
+    sender: SyncSender<Vec<f32>>,
+    /// Buffers the writer is done with, so that the audio thread doesn't have to allocate.
+    /// Only accessed through `get_mut`, the mutex just makes the recorder `Sync`.
+    free_buffers: Mutex<Receiver<Vec<f32>>>,
+    writer: JoinHandle<Result<usize>>,
+    pub path: PathBuf,
+    frames_recorded: usize,
+    /// Audio which couldn't be handed over because the writer fell behind
+    frames_dropped: usize,
+}
+
+pub struct Recording {
+    pub path: PathBuf,
+    pub duration: Duration,
+    /// In bytes
+    pub size: usize,
+}
+
+impl Recorder {
+    pub fn start(path: PathBuf, format: AudioFormat) -> Result<Self> {
+        let mut encoder = format.encoder()?;
+        // Fail right away if the file can't be created, rather than when the recording stops
+        let mut file = File::create(&path)?;
+        let (sender, receiver) = sync_channel::<Vec<f32>>(BUFFER_CHUNKS);
+        let (free_sender, free_buffers) = sync_channel::<Vec<f32>>(BUFFER_CHUNKS);
+        for _ in 0..BUFFER_CHUNKS {
+            free_sender.send(Vec::with_capacity(CHUNK_SAMPLES))?;
+        }
+
+        let writer = thread::Builder::new().name("recorder".to_owned()).spawn(move || {
+            let mut size = 0;
+            // Ends once the sender is dropped
+            for samples in receiver {
+                encoder.write(&samples)?;
+                let _ = free_sender.try_send(samples);
+                if encoder.len() - size >= FLUSH_SIZE {
+                    let data = encoder.take_encoded();
+                    file.write_all(&data)?;
+                    size += data.len();
+                }
+            }
+            let data = encoder.finish()?;
+            file.write_all(&data)?;
+
+            Ok(size + data.len())
+        })?;
+
+        Ok(Self { sender, free_buffers: Mutex::new(free_buffers), writer, path, frames_recorded: 0, frames_dropped: 0 })
+    }
+
+    /// Called from the audio thread, never blocks or allocates.
+    /// Returns false once the recording has reached its maximum length, the writer has fallen behind or failed.
+    pub fn write(&mut self, samples: &[f32]) -> bool {
+        if self.frames_recorded >= MAX_RECORDING_SECONDS * SAMPLE_RATE as usize {
+            return false;
+        }
+        let free_buffers = self.free_buffers.get_mut().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut buffer = match free_buffers.try_recv() {
+            Ok(buffer) => buffer,
+            // Every buffer is still waiting to be encoded
+            Err(TryRecvError::Empty) => {
+                self.frames_dropped += samples.len() / 2;
+                return false;
+            },
+            Err(TryRecvError::Disconnected) => return false,
+        };
+        buffer.clear();
+        buffer.extend_from_slice(samples);
+        self.frames_recorded += samples.len() / 2;
+
+        self.sender.try_send(buffer).is_ok()
+    }
+
+    /// Why the recording has stopped after `write` returned false, for the announcement
+    pub fn stop_reason(&self) -> &'static str {
+        if self.frames_dropped > 0 {
+            "as the bot couldn't save it fast enough"
+        } else {
+            "at the maximum length"
+        }
+    }
+
+    /// Waits for the writer to finish the file. Blocking, run it off the async runtime.
+    pub fn stop(self) -> Result<Recording> {
+        let Recorder { sender, writer, path, frames_recorded, frames_dropped, .. } = self;
+        drop(sender);
+        let size = writer.join().map_err(|_| anyhow!("The recording writer has crashed"))??;
+        if frames_dropped > 0 {
+            log::warn!("Recording {} fell behind, {frames_dropped} frames were dropped", path.display());
+        }
+
+        Ok(Recording {
+            path,
+            duration: Duration::from_secs_f64(frames_recorded as f64 / SAMPLE_RATE as f64),
+            size,
+        })
+    }
+}
+
+impl Recording {
+    pub fn describe(&self) -> String {
+        let filename = self.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        format!(
+            "saved as `{filename}` ({}, {:.1} MiB)",
+            format_duration(self.duration),
+            self.size as f64 / 1024.0 / 1024.0
+        )
+    }
+}
+
+/// Stops a recording which ended without /record stop, and tells the session's text channel about it.
+pub async fn stop_and_announce(ctx: &Context, channel_id: ChannelId, recorder: Recorder, reason: &str) {
+    let content = match tokio::task::spawn_blocking(move || recorder.stop()).await {
+        Ok(Ok(recording)) => format!("⏹️ Recording stopped {reason}, {}", recording.describe()),
+        Ok(Err(err)) => {
+            log::error!("Recording failed: {err}");
+            "⏹️ Recording failed, the file may be incomplete".to_owned()
+        },
+        Err(err) => {
+            log::error!("Recording task failed: {err}");
+            "⏹️ Recording failed, the file may be incomplete".to_owned()
+        },
+    };
+    let _ = channel_id.send_message(ctx, CreateMessage::new().content(content)).await;
+}
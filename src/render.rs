@@ -0,0 +1,223 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Offline rendering of modules into audio files, using the same settings as playback.
+
+use std::{ffi::CString, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use libopenmpt_sys::{openmpt_module_ctl_set_boolean, openmpt_module_ctl_set_floatingpoint, openmpt_module_ctl_set_text, openmpt_module_read_interleaved_float_stereo, openmpt_module_set_render_param, OPENMPT_MODULE_RENDER_INTERPOLATIONFILTER_LENGTH, OPENMPT_MODULE_RENDER_STEREOSEPARATION_PERCENT};
+
+use crate::decoder::{self, Decoder, OpenMptModuleExt, OpenMptModuleSafe};
+use crate::encoder::{AudioFormat, SAMPLE_RATE};
+use crate::session::{Interpolation, VoiceSessionData};
+
+/// Rendering longer modules would take too long and produce files too large to upload anyway
+pub const MAX_RENDER_SECONDS: f64 = 30.0 * 60.0;
+/// 100 ms
+const CHUNK_FRAMES: usize = SAMPLE_RATE as usize / 10;
+/// Looped samples never end on their own
+const MAX_SAMPLE_SECONDS: usize = 30;
+/// A sample is considered over after this much silence
+const SAMPLE_SILENCE_FRAMES: usize = SAMPLE_RATE as usize / 4;
+/// C-5, samples play at their own pitch on it
+const MIDDLE_C: i32 = 60;
+
+/// libopenmpt settings a session can change
+#[derive(Clone)]
+pub struct RenderSettings {
+    pub interpolation: Interpolation,
+    pub amiga_enabled: bool,
+    pub amiga_mode: String,
+    /// 0 is mono, 100 is the module's own separation, 200 is the maximum
+    pub stereo_separation: i32,
+    /// Sessions have no way to change it yet, only offline renders do
+    pub tempo_factor: f64,
+    /// Play all subsongs one after another, starting with the selected one
+    pub autosubsong: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            interpolation: Interpolation::Default,
+            amiga_enabled: false,
+            amiga_mode: "auto".to_owned(),
+            stereo_separation: 100,
+            tempo_factor: 1.0,
+            autosubsong: false,
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn from_session(data: &VoiceSessionData) -> Self {
+        Self {
+            interpolation: data.interpolation,
+            amiga_enabled: data.amiga_enabled,
+            amiga_mode: data.amiga_mode.clone(),
+            stereo_separation: data.stereo_separation,
+            tempo_factor: 1.0,
+            autosubsong: data.autosubsong_enabled,
+        }
+    }
+
+    pub fn apply(&self, module: &OpenMptModuleSafe) {
+        unsafe {
+            openmpt_module_set_render_param(
+                module.0,
+                OPENMPT_MODULE_RENDER_INTERPOLATIONFILTER_LENGTH as std::os::raw::c_int,
+                self.interpolation.to_openmpt_value()
+            );
+            openmpt_module_set_render_param(
+                module.0,
+                OPENMPT_MODULE_RENDER_STEREOSEPARATION_PERCENT as std::os::raw::c_int,
+                self.stereo_separation
+            );
+
+            let ctl = CString::new("render.resampler.emulate_amiga").unwrap();
+            openmpt_module_ctl_set_boolean(module.0, ctl.as_ptr(), self.amiga_enabled as i32);
+
+            let ctl = CString::new("render.resampler.emulate_amiga_type").unwrap();
+            let value = CString::new(self.amiga_mode.clone()).unwrap();
+            openmpt_module_ctl_set_text(module.0, ctl.as_ptr(), value.as_ptr());
+
+            let ctl = CString::new("play.tempo_factor").unwrap();
+            openmpt_module_ctl_set_floatingpoint(module.0, ctl.as_ptr(), self.tempo_factor);
+        }
+    }
+}
+
+/// What happened while rendering a chunk
+#[derive(Debug, PartialEq)]
+pub enum RenderEvent {
+    Playing,
+    /// The subsong has ended and the next one has been selected, the rest of the buffer is left as is
+    NextSubsong(i32),
+    /// The module has ended, the rest of the buffer is left as is
+    Ended,
+}
+
+/// Renders the next chunk of interleaved stereo samples, moving on to the next subsong if autosubsong is enabled.
+/// Voice sessions and offline renders both go through here, so that they sound the same.
+/// Returns the amount of frames rendered.
+pub fn render_chunk(module: &mut dyn Decoder, autosubsong: bool, sample_rate: i32, buffer: &mut [f32]) -> (usize, RenderEvent) {
+    let frames_read = module.read(sample_rate, buffer);
+    if frames_read == buffer.len()/2 {
+        return (frames_read, RenderEvent::Playing);
+    }
+
+    let current_subsong = module.selected_subsong();
+    if !autosubsong || current_subsong >= module.num_subsongs() - 1 {
+        return (frames_read, RenderEvent::Ended);
+    }
+    module.select_subsong(current_subsong + 1);
+
+    (frames_read, RenderEvent::NextSubsong(current_subsong + 1))
+}
+
+/// Renders a module file into the given format. Blocking, run it off the async runtime.
+/// Fails once the output grows larger than `max_size` bytes or longer than `max_seconds`.
+pub fn render(data: &Arc<[u8]>, settings: &RenderSettings, subsong: i32, format: AudioFormat, max_size: usize, max_seconds: f64) -> Result<Vec<u8>> {
+    let mut module = decoder::open(data)?;
+    if let Some(openmpt) = module.openmpt() {
+        settings.apply(openmpt);
+    }
+    if !module.select_subsong(subsong) {
+        return Err(anyhow!("The specified subsong number is out of range"));
+    }
+    if module.duration_seconds() > max_seconds {
+        return Err(anyhow!("Modules longer than {} minutes can't be rendered", max_seconds as u32 / 60));
+    }
+
+    let mut encoder = format.encoder()?;
+    let mut buffer = vec![0f32; CHUNK_FRAMES * 2];
+    let mut frames_rendered = 0;
+    loop {
+        let (frames_read, event) = render_chunk(module.as_mut(), settings.autosubsong, SAMPLE_RATE as i32, &mut buffer);
+        encoder.write(&buffer[..frames_read * 2])?;
+        frames_rendered += frames_read;
+
+        if encoder.len() > max_size {
+            return Err(anyhow!(
+                "The rendered file is larger than the upload limit of {} MiB, try a smaller format such as Opus",
+                max_size / 1024 / 1024
+            ));
+        }
+        // Some modules don't know how long they are
+        if event == RenderEvent::Ended || frames_rendered as f64 / SAMPLE_RATE as f64 > max_seconds {
+            break;
+        }
+    }
+
+    encoder.finish()
+}
+
+/// Renders a single note of a sample, or of an instrument if the module has instruments, into a WAV file.
+/// libopenmpt doesn't give access to sample data, so the note is played on top of the muted song.
+/// Blocking, run it off the async runtime.
+pub fn render_sample(data: &Arc<[u8]>, index: i32, max_size: usize) -> Result<Vec<u8>> {
+    let mut module = OpenMptModuleExt::from_memory(data)
+        .ok_or(anyhow!("Only tracker modules have samples"))?;
+    let settings = RenderSettings { interpolation: Interpolation::Sinc8, ..Default::default() };
+    settings.apply(module.module());
+    unsafe {
+        // Otherwise rendering stops at the end of the song, possibly in the middle of the note
+        let ctl = CString::new("play.at_end").unwrap();
+        let value = CString::new("continue").unwrap();
+        openmpt_module_ctl_set_text(module.module().0, ctl.as_ptr(), value.as_ptr());
+    }
+    for channel in 0..module.num_channels() {
+        module.set_channel_mute(channel, true);
+    }
+    if module.play_note(index, MIDDLE_C, 1.0, 0.0).is_none() {
+        return Err(anyhow!("Unable to play this sample"));
+    }
+
+    let mut frames: Vec<f32> = Vec::new();
+    let mut last_audible_frame = None;
+    let mut buffer = vec![0f32; CHUNK_FRAMES * 2];
+    while frames.len() < MAX_SAMPLE_SECONDS * SAMPLE_RATE as usize * 2 {
+        let frames_read = unsafe {
+            openmpt_module_read_interleaved_float_stereo(module.module().0, SAMPLE_RATE as i32, CHUNK_FRAMES, buffer.as_mut_ptr())
+        };
+        for (i, frame) in buffer[..frames_read * 2].chunks_exact(2).enumerate() {
+            if frame[0].abs() > f32::EPSILON || frame[1].abs() > f32::EPSILON {
+                last_audible_frame = Some(frames.len() / 2 + i);
+            }
+        }
+        frames.extend_from_slice(&buffer[..frames_read * 2]);
+
+        let silent_frames = frames.len() / 2 - last_audible_frame.map_or(0, |frame| frame + 1);
+        if frames_read == 0 || silent_frames >= SAMPLE_SILENCE_FRAMES {
+            break;
+        }
+    }
+
+    let Some(last_audible_frame) = last_audible_frame else {
+        return Err(anyhow!("This sample is empty"));
+    };
+    let mut encoder = AudioFormat::Wav.encoder()?;
+    encoder.write(&frames[..(last_audible_frame + 1) * 2])?;
+    if encoder.len() > max_size {
+        return Err(anyhow!("The sample is larger than the upload limit of {} MiB", max_size / 1024 / 1024));
+    }
+
+    encoder.finish()
+}
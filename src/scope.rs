@@ -0,0 +1,169 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Per-channel oscilloscope animations. Every channel is rendered on its own by muting the others,
+// then drawn as a grid of scopes into an animated GIF.
+
+use std::{borrow::Cow, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use libopenmpt_sys::{openmpt_module_read_float_mono, openmpt_module_select_subsong, openmpt_module_set_position_seconds};
+
+use crate::decoder::OpenMptModuleExt;
+use crate::nowplaying::EMBED_COLOUR;
+use crate::render::RenderSettings;
+
+pub const MAX_SCOPE_SECONDS: i64 = 20;
+const FRAMES_PER_SECOND: usize = 20;
+const SAMPLE_RATE: usize = 24000;
+/// Each scope shows 20 ms of its channel
+const WINDOW: usize = SAMPLE_RATE / 50;
+const WIDTH: usize = 800;
+/// Channels quieter than this are drawn as a flat line instead of amplified noise
+const SILENCE: f32 = 1e-4;
+
+const BACKGROUND: u8 = 0;
+const GRID: u8 = 1;
+const TRACE: u8 = 2;
+
+/// Scopes are laid out in a square-ish grid, each three times as wide as it's tall
+struct Layout {
+    columns: usize,
+    cell_width: usize,
+    cell_height: usize,
+    height: usize,
+}
+
+impl Layout {
+    fn new(num_channels: usize) -> Self {
+        let columns = (num_channels as f64).sqrt().ceil() as usize;
+        let rows = num_channels.div_ceil(columns);
+        let cell_width = WIDTH / columns;
+        let cell_height = cell_width / 3;
+
+        Self { columns, cell_width, cell_height, height: rows * cell_height }
+    }
+}
+
+/// Renders `seconds` of the module from `start_seconds` on. Blocking, run it off the async runtime.
+/// Fails once the animation grows larger than `max_size` bytes.
+pub fn scope(data: &Arc<[u8]>, settings: &RenderSettings, subsong: i32, start_seconds: f64, seconds: usize, max_size: usize) -> Result<Vec<u8>> {
+    let mut module = OpenMptModuleExt::from_memory(data)
+        .ok_or(anyhow!("Only tracker modules can be drawn as scopes"))?;
+    settings.apply(module.module());
+    unsafe { openmpt_module_select_subsong(module.module().0, subsong) };
+
+    let num_channels = module.num_channels().max(0) as usize;
+    if num_channels == 0 {
+        return Err(anyhow!("This module has no channels"));
+    }
+
+    // A bit more than needed, the last frame looks ahead for a trigger point
+    let num_samples = seconds * SAMPLE_RATE + WINDOW * 2;
+    let mut channels: Vec<Vec<f32>> = Vec::with_capacity(num_channels);
+    for channel in 0..num_channels {
+        for other in 0..num_channels {
+            module.set_channel_mute(other as i32, other != channel);
+        }
+        let mut samples = vec![0f32; num_samples];
+        unsafe {
+            openmpt_module_set_position_seconds(module.module().0, start_seconds);
+            openmpt_module_read_float_mono(module.module().0, SAMPLE_RATE as i32, samples.len(), samples.as_mut_ptr());
+        }
+
+        // Quiet channels would be hard to see otherwise
+        let peak = samples.iter().fold(0f32, |peak, sample| peak.max(sample.abs()));
+        if peak > SILENCE {
+            samples.iter_mut().for_each(|sample| *sample /= peak);
+        } else {
+            samples.fill(0.0);
+        }
+        channels.push(samples);
+    }
+
+    let layout = Layout::new(num_channels);
+    let accent = EMBED_COLOUR.to_be_bytes();
+    let palette = [
+        0x2B, 0x2D, 0x31,
+        0x4E, 0x50, 0x58,
+        accent[1], accent[2], accent[3],
+    ];
+    let mut encoder = gif::Encoder::new(Vec::new(), WIDTH as u16, layout.height as u16, &palette)?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+
+    let mut pixels = vec![BACKGROUND; WIDTH * layout.height];
+    for frame in 0..seconds * FRAMES_PER_SECOND {
+        let offset = frame * SAMPLE_RATE / FRAMES_PER_SECOND;
+        pixels.fill(BACKGROUND);
+        for (channel, samples) in channels.iter().enumerate() {
+            let left = (channel % layout.columns) * layout.cell_width;
+            let top = (channel / layout.columns) * layout.cell_height;
+            draw_scope(&mut pixels, &layout, left, top, &samples[offset..]);
+        }
+
+        encoder.write_frame(&gif::Frame {
+            width: WIDTH as u16,
+            height: layout.height as u16,
+            buffer: Cow::Borrowed(&pixels),
+            delay: (100 / FRAMES_PER_SECOND) as u16,
+            ..Default::default()
+        })?;
+        if encoder.get_ref().len() > max_size {
+            return Err(anyhow!(
+                "The animation is larger than the upload limit of {} MiB, try a shorter length",
+                max_size / 1024 / 1024
+            ));
+        }
+    }
+
+    Ok(encoder.into_inner()?)
+}
+
+fn draw_scope(pixels: &mut [u8], layout: &Layout, left: usize, top: usize, samples: &[f32]) {
+    // Start at a rising zero crossing, so that periodic waves stand still
+    let start = (1..WINDOW)
+        .find(|i| samples[i - 1] <= 0.0 && samples[*i] > 0.0)
+        .unwrap_or(0);
+
+    let centre = top + layout.cell_height / 2;
+    let amplitude = (layout.cell_height / 2).saturating_sub(2) as f32;
+    let mut previous_y = None;
+    for x in 0..layout.cell_width - 1 {
+        let sample = samples[start + x * WINDOW / layout.cell_width];
+        let y = (centre as f32 - sample * amplitude) as usize;
+        // Connect to the previous point, so that steep edges stay visible
+        let (from, to) = match previous_y {
+            Some(previous_y) if previous_y < y => (previous_y, y),
+            Some(previous_y) => (y, previous_y),
+            None => (y, y),
+        };
+        for y in from..=to {
+            pixels[y * WIDTH + left + x] = TRACE;
+        }
+        previous_y = Some(y);
+    }
+
+    // Right and bottom edges separate the scopes
+    for y in top..top + layout.cell_height {
+        pixels[y * WIDTH + left + layout.cell_width - 1] = GRID;
+    }
+    for x in left..left + layout.cell_width {
+        pixels[(top + layout.cell_height - 1) * WIDTH + x] = GRID;
+    }
+}
@@ -17,38 +17,109 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::VecDeque, ffi::CString, io::{Read, Seek}, sync::Arc};
+use std::{collections::VecDeque, io::{Read, Seek}, sync::Arc};
 
-use libopenmpt_sys::{openmpt_module, openmpt_module_ctl_set_boolean, openmpt_module_ctl_set_text, openmpt_module_destroy, openmpt_module_get_metadata, openmpt_module_get_num_subsongs, openmpt_module_get_selected_subsong, openmpt_module_read_interleaved_float_stereo, openmpt_module_select_subsong, openmpt_module_set_render_param, OPENMPT_MODULE_RENDER_INTERPOLATIONFILTER_LENGTH};
-use serenity::{all::{ChannelId, Context, CreateMessage, GuildId}, prelude::TypeMap};
+use serenity::{all::{ChannelId, Context, CreateMessage, GuildId, UserId}, prelude::TypeMap};
 use songbird::{input::RawAdapter, Call};
 use symphonia::core::io::MediaSource;
-use tokio::{spawn, sync::{mpsc::{channel, Receiver, Sender}, Mutex, RwLock}};
-use anyhow::Result;
-
-use crate::{botdata::BotDataKey, misc::escape_markdown};
-
-// Raw FFI in Rust kinda sucks
-// To ensure safety, please use the module in ONLY one session!!!
-unsafe impl Send for OpenMptModuleSafe {}
-unsafe impl Sync for OpenMptModuleSafe {}
-pub struct OpenMptModuleSafe(pub *mut openmpt_module);
-impl Drop for OpenMptModuleSafe {
-    fn drop(&mut self) {
-        unsafe { openmpt_module_destroy(self.0); }
+use tokio::{spawn, task::{spawn_blocking, JoinHandle}, sync::{mpsc::{channel, Receiver, Sender}, Mutex, RwLock}};
+use anyhow::{anyhow, Result};
+
+use crate::{botdata::BotDataKey, decoder::{self, Decoder}, fx::FxChain, misc::filename_from_url, nowplaying, recorder::{self, Recorder}, render::{render_chunk, RenderEvent, RenderSettings}};
+
+pub struct WrappedModule {
+    pub filename: String,
+    pub filehash: String,
+    /// Where the module was downloaded from
+    pub url: String,
+    /// The module file itself
+    pub data: Arc<[u8]>,
+    /// The user who has requested the module
+    pub requester: UserId,
+    pub module: Box<dyn Decoder>
+}
+
+impl WrappedModule {
+    /// Module title, or the file name if the module has no title.
+    pub fn title(&self) -> String {
+        let title = self.module.metadata("title");
+        if title.is_empty() {
+            return self.filename.clone();
+        }
+
+        title
     }
 }
 
-pub struct WrappedModule {
+/// A module waiting in the queue.
+/// Only the module file and some metadata are kept, the libopenmpt instance
+/// is created once the module is about to be played.
+pub struct QueuedModule {
     pub filename: String,
     pub filehash: String,
-    pub module: OpenMptModuleSafe
+    pub url: String,
+    pub data: Arc<[u8]>,
+    pub requester: UserId,
+    /// Module title, or the file name if the module has no title
+    pub title: String,
+    pub duration: f64,
+    pub format: String,
+    /// Only the next module in the queue is preloaded
+    pub(crate) preloaded: Option<Box<dyn Decoder>>
+}
+
+impl QueuedModule {
+    /// Loads a downloaded module file with a decoder suitable for its format and reads its metadata.
+    /// The decoder is kept in case the module is going to play next, `enqueue` drops it otherwise.
+    /// Blocking, run it off the async runtime.
+    pub fn probe(bytes: Vec<u8>, url: &str, requester: UserId) -> Result<Self> {
+        let filehash = sha256::digest(&bytes);
+        let data: Arc<[u8]> = Arc::from(bytes);
+        let module = decoder::open(&data)?;
+
+        let filename = filename_from_url(url);
+        let title = match module.metadata("title") {
+            title if title.is_empty() => filename.clone(),
+            title => title,
+        };
+
+        Ok(Self {
+            title,
+            duration: module.duration_seconds(),
+            format: module.metadata("type_long"),
+            filename,
+            filehash,
+            url: url.to_owned(),
+            data,
+            requester,
+            preloaded: Some(module),
+        })
+    }
+
+    /// Returns None if the module hasn't been preloaded yet, see [preload_next_in_queue].
+    pub fn into_wrapped(self) -> Option<WrappedModule> {
+        let module = self.preloaded?;
+
+        Some(WrappedModule {
+            filename: self.filename,
+            filehash: self.filehash,
+            url: self.url,
+            data: self.data,
+            requester: self.requester,
+            module,
+        })
+    }
 }
 
 pub enum VoiceSessionNotificationMessage {
     EndOfQueue,
-    PlayingNextInQueue(String),
+    /// A new module has started playing
+    NowPlaying,
     PlayingSubsong(i32),
+    /// The recording has reached its maximum length, fallen behind or failed, and has been taken out of the session
+    RecordingStopped(Recorder),
+    /// The next module in the queue has to be loaded before it can start
+    LoadNextInQueue,
     Leave,
 }
 
@@ -57,6 +128,7 @@ pub enum VoiceSessionControlMessage {
     // TODO
 }
 
+#[derive(Clone, Copy)]
 pub enum Interpolation {
     Default,
     None,
@@ -66,6 +138,17 @@ pub enum Interpolation {
 }
 
 impl Interpolation {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Interpolation::Default),
+            "none" => Some(Interpolation::None),
+            "linear" => Some(Interpolation::Linear),
+            "cubic" => Some(Interpolation::Cubic),
+            "sinc8" => Some(Interpolation::Sinc8),
+            _ => None
+        }
+    }
+
     pub fn to_openmpt_value(&self) -> i32 {
         match self {
             Interpolation::Default => 0,
@@ -94,12 +177,44 @@ pub struct VoiceSessionData {
     pub(crate) interpolation: Interpolation,
     pub(crate) amiga_enabled: bool,
     pub(crate) amiga_mode: String,
+    pub(crate) stereo_separation: i32,
     pub(crate) autosubsong_enabled: bool,
     // pub(crate) context: Context,
     pub(crate) text_channel_id: ChannelId,
     pub(crate) notification_handle: Sender<VoiceSessionNotificationMessage>,
-    pub(crate) module_queue: VecDeque<WrappedModule>,
-    pub current_vote: Option<crate::vote::Vote>
+    pub(crate) module_queue: VecDeque<QueuedModule>,
+    pub current_vote: Option<crate::vote::Vote>,
+    /// Receives everything the session plays, see /record
+    pub(crate) recorder: Option<Recorder>,
+    /// Post-processing of everything the session plays, see /fx
+    pub(crate) fx: FxChain,
+}
+
+impl VoiceSessionData {
+    /// Starts playing the module if nothing is playing, otherwise puts it into the queue.
+    /// Returns true if the module has started playing right away.
+    pub fn enqueue(&mut self, mut module: QueuedModule) -> Result<bool> {
+        if let Some(playing_module) = &self.current_module {
+            if module.filehash == playing_module.filehash {
+                return Err(anyhow!("This module is already being played"));
+            }
+        }
+        if self.module_queue.iter().any(|queued_module| queued_module.filehash == module.filehash) {
+            return Err(anyhow!("This module already exists in the queue"));
+        }
+
+        // Only the next module to play stays loaded, the rest would just take up memory
+        if !self.module_queue.is_empty() {
+            module.preloaded = None;
+        }
+        self.module_queue.push_back(module);
+        if self.current_module.is_some() {
+            return Ok(false);
+        }
+        start_next_in_queue(self);
+
+        Ok(self.current_module.is_some())
+    }
 }
 
 #[derive(Clone)]
@@ -120,45 +235,74 @@ impl VoiceSession {
         let (tx, mut rx) = tokio::sync::mpsc::channel::<VoiceSessionNotificationMessage>(64);
         let ctx2 = ctx.clone();
         let text_channel_id2 = text_channel_id.clone();
+        let (control_tx, control_rx) = channel::<VoiceSessionControlMessage>(8);
+        let data = Arc::new(RwLock::new(VoiceSessionData {
+            current_module: None,
+            paused: false,
+            interpolation: Interpolation::Default,
+            amiga_enabled: false,
+            amiga_mode: "auto".to_owned(),
+            stereo_separation: 100,
+            autosubsong_enabled: false,
+            // context: ctx.clone(),
+            text_channel_id,
+            notification_handle: tx,
+            module_queue: VecDeque::with_capacity(16),
+            current_vote: None,
+            recorder: None,
+            fx: FxChain::default(),
+        }));
+        let data2 = data.clone();
 
         spawn(async move {
+            // Keeps the "now playing" card of the current module up to date
+            let mut now_playing_updater: Option<JoinHandle<()>> = None;
             loop {
                 if let Some(message) = rx.recv().await {
                     match message {
                         VoiceSessionNotificationMessage::EndOfQueue => {
+                            if let Some(updater) = now_playing_updater.take() {
+                                updater.abort();
+                            }
                             let _ = text_channel_id2.send_message(&ctx2, CreateMessage::new().content("End of queue reached, no more songs to play"))
                                 .await;
                             },
-                        VoiceSessionNotificationMessage::Leave => break,
-                        VoiceSessionNotificationMessage::PlayingNextInQueue(title) => {
-                            let mut escaped_title = escape_markdown(&title);
-                            let _ = text_channel_id2.send_message(&ctx2, CreateMessage::new().content("Now playing: **".to_string()+&escaped_title+"**"))
-                                .await;
+                        VoiceSessionNotificationMessage::Leave => {
+                            if let Some(updater) = now_playing_updater.take() {
+                                updater.abort();
+                            }
+                            break;
+                        },
+                        VoiceSessionNotificationMessage::NowPlaying => {
+                            if let Some(updater) = now_playing_updater.take() {
+                                updater.abort();
+                            }
+                            now_playing_updater = nowplaying::announce(&ctx2, text_channel_id2, data2.clone()).await;
+                            preload_next_in_queue(&data2).await;
                         },
                         VoiceSessionNotificationMessage::PlayingSubsong(subsong_number) => {
                             let _ = text_channel_id2.send_message(&ctx2, CreateMessage::new().content(format!("Playing subsong {subsong_number}")))
                                 .await;
-                        }
+                        },
+                        VoiceSessionNotificationMessage::RecordingStopped(recorder) => {
+                            let reason = recorder.stop_reason();
+                            recorder::stop_and_announce(&ctx2, text_channel_id2, recorder, reason).await;
+                        },
+                        VoiceSessionNotificationMessage::LoadNextInQueue => {
+                            preload_next_in_queue(&data2).await;
+                            let mut data_l = data2.write().await;
+                            // Something else might have started playing in the meantime
+                            if data_l.current_module.is_none() {
+                                start_next_in_queue(&mut data_l);
+                            }
+                        },
                     };
                 }
             }
         });
 
-        let (control_tx, control_rx) = channel::<VoiceSessionControlMessage>(8);
         let this = Self {
-            data: Arc::new(RwLock::new(VoiceSessionData {
-                current_module: None,
-                paused: false,
-                interpolation: Interpolation::Default,
-                amiga_enabled: false,
-                amiga_mode: "auto".to_owned(),
-                autosubsong_enabled: false,
-                // context: ctx.clone(),
-                text_channel_id,
-                notification_handle: tx,
-                module_queue: VecDeque::with_capacity(16),
-                current_vote: None,
-            })),
+            data,
             control_rx,
             control_tx: control_tx.clone(),
         };
@@ -168,38 +312,68 @@ impl VoiceSession {
 
     fn play_next_in_queue(&self) {
         let mut data_l = self.data.blocking_write();
-        if data_l.module_queue.len() == 0 {
-            data_l.current_module = None;
-            data_l.notification_handle.blocking_send(VoiceSessionNotificationMessage::EndOfQueue).unwrap();
-        } else {
-            let Some(queued_module) = data_l.module_queue.pop_front() else { unreachable!() };
-            data_l.current_module = Some(queued_module);
-            let Some(current_module) = &data_l.current_module else { unreachable!() };
-            unsafe {
-                openmpt_module_set_render_param(
-                    current_module.module.0,
-                    OPENMPT_MODULE_RENDER_INTERPOLATIONFILTER_LENGTH as std::os::raw::c_int,
-                    data_l.interpolation.to_openmpt_value()
-                );
-
-                let ctl = CString::new("render.resampler.emulate_amiga").unwrap();
-                openmpt_module_ctl_set_boolean(current_module.module.0, ctl.as_ptr(), data_l.amiga_enabled as i32);
-
-                let ctl = CString::new("render.resampler.emulate_amiga_type").unwrap();
-                let value = CString::new(data_l.amiga_mode.clone()).unwrap();
-                openmpt_module_ctl_set_text(current_module.module.0, ctl.as_ptr(), value.as_ptr());
-            };
-            let Some(module) = &data_l.current_module else { unreachable!() };
-
-            let key = std::ffi::CString::new("title").unwrap();
-            let title_raw = unsafe {openmpt_module_get_metadata(module.module.0, key.as_ptr())};
-            let mut module_title = unsafe {std::ffi::CStr::from_ptr(title_raw)}
-                .to_str().unwrap();
-            if module_title.is_empty() {
-                module_title = &module.filename;
-            }
+        data_l.current_module = None;
+        start_next_in_queue(&mut data_l);
+    }
+}
+
+/// Starts the next module in the queue if it has been preloaded, otherwise leaves loading it
+/// to the notification task, as this may run on the audio thread.
+fn start_next_in_queue(data: &mut VoiceSessionData) {
+    let Some(next_module) = data.module_queue.front() else {
+        let _ = data.notification_handle.try_send(VoiceSessionNotificationMessage::EndOfQueue);
+        return;
+    };
+    if next_module.preloaded.is_none() {
+        if let Err(err) = data.notification_handle.try_send(VoiceSessionNotificationMessage::LoadNextInQueue) {
+            log::error!("Unable to load the next module in the queue: {err}");
+        }
+        return;
+    }
+
+    data.current_module = data.module_queue.pop_front().and_then(QueuedModule::into_wrapped);
+    let Some(current_module) = &data.current_module else { unreachable!() };
+    if let Some(module) = current_module.module.openmpt() {
+        RenderSettings::from_session(data).apply(module);
+    }
 
-            data_l.notification_handle.blocking_send(VoiceSessionNotificationMessage::PlayingNextInQueue(module_title.to_string())).unwrap();
+    let _ = data.notification_handle.try_send(VoiceSessionNotificationMessage::NowPlaying);
+}
+
+/// Creates the libopenmpt instance of the next module in the queue ahead of time,
+/// so that the audio thread does not have to wait for it.
+/// Should be called after every change to the queue.
+pub async fn preload_next_in_queue(data: &RwLock<VoiceSessionData>) {
+    loop {
+        let data_l = data.read().await;
+        let Some(next_module) = data_l.module_queue.front() else { return };
+        if next_module.preloaded.is_some() {
+            return;
+        }
+        let module_data = next_module.data.clone();
+        let filehash = next_module.filehash.clone();
+        drop(data_l);
+
+        let module = spawn_blocking(move || decoder::open(&module_data)).await
+            .map_err(anyhow::Error::from)
+            .and_then(|module| module);
+
+        let mut data_l = data.write().await;
+        // The queue might have changed in the meantime
+        let Some(next_module) = data_l.module_queue.front_mut() else { return };
+        if next_module.filehash != filehash {
+            continue;
+        }
+        match module {
+            Ok(module) => {
+                next_module.preloaded = Some(module);
+                return;
+            },
+            Err(err) => {
+                // Unlikely, it has been loaded once before being queued
+                log::error!("Unable to load a queued module, skipping it: {err}");
+                data_l.module_queue.pop_front();
+            },
         }
     }
 }
@@ -208,23 +382,37 @@ impl Read for VoiceSession {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         let (_r_begin, floats, _r_end) = unsafe { buf.align_to_mut::<f32>() };
         floats.fill(0.0); // Fill with silence
-        let data_l = self.data.blocking_write();
-        if let Some(module_wrapped) = &data_l.current_module {
-            if !data_l.paused {
-                unsafe {
-                    let frames_read = openmpt_module_read_interleaved_float_stereo(module_wrapped.module.0, 48000, floats.len()/2, floats.as_mut_ptr());
-                    if frames_read < floats.len()/2 {
-                        if !data_l.autosubsong_enabled {
-                            self.control_tx.blocking_send(VoiceSessionControlMessage::PlayNextInQueue).unwrap();
-                        } else {
-                            let current_subsong = openmpt_module_get_selected_subsong(module_wrapped.module.0);
-                            if current_subsong == openmpt_module_get_num_subsongs(module_wrapped.module.0) - 1 {
-                                self.control_tx.blocking_send(VoiceSessionControlMessage::PlayNextInQueue).unwrap();
-                            } else {
-                                openmpt_module_select_subsong(module_wrapped.module.0, current_subsong+1);
-                                data_l.notification_handle.blocking_send(VoiceSessionNotificationMessage::PlayingSubsong(current_subsong + 1)).unwrap();
+        let mut data_l = self.data.blocking_write();
+        let data = &mut *data_l;
+        if let Some(module_wrapped) = &mut data.current_module {
+            if !data.paused {
+                match render_chunk(module_wrapped.module.as_mut(), data.autosubsong_enabled, 48000, floats).1 {
+                    RenderEvent::Playing => {},
+                    RenderEvent::NextSubsong(subsong) => {
+                        data.notification_handle.blocking_send(VoiceSessionNotificationMessage::PlayingSubsong(subsong)).unwrap();
+                    },
+                    RenderEvent::Ended => {
+                        self.control_tx.blocking_send(VoiceSessionControlMessage::PlayNextInQueue).unwrap();
+                    },
+                }
+            }
+        }
+        // Runs during silence too, so that reverb tails aren't cut off
+        data.fx.process(floats);
+        // Silence included, so that the recording stays in sync with what listeners heard
+        if let Some(recorder) = &mut data.recorder {
+            if !recorder.write(floats) {
+                let recorder = data.recorder.take().unwrap();
+                // Never block the audio thread, the notification task may also be gone after leaving
+                if let Err(err) = data.notification_handle.try_send(VoiceSessionNotificationMessage::RecordingStopped(recorder)) {
+                    log::error!("Unable to announce the end of a recording: {err}");
+                    if let VoiceSessionNotificationMessage::RecordingStopped(recorder) = err.into_inner() {
+                        // The file still has to be finished
+                        std::thread::spawn(move || {
+                            if let Err(err) = recorder.stop() {
+                                log::error!("Recording failed: {err}");
                             }
-                        }
+                        });
                     }
                 }
             }
@@ -262,7 +450,7 @@ impl MediaSource for VoiceSession {
 pub async fn initiate_session(ctx: &Context, guild_id: GuildId, voice_channel_id: ChannelId, text_channel_id: ChannelId) -> Result<VoiceSessionHandle> {
     {
         let mut lock = ctx.data.write().await;
-        let botdata = lock.get_mut::<crate::BotDataKey>().unwrap();
+        let botdata = lock.get_mut::<BotDataKey>().unwrap();
         if let Some(_) = botdata.sessions.get(&guild_id) {
             return Err(anyhow::anyhow!("The bot is already in the voice channel or a session already exists for this guild id"));
         }
@@ -0,0 +1,117 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Stems for remixers: every channel rendered on its own, packed into a ZIP file.
+
+use std::{fs::File, io::Write, path::Path, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::decoder::{Decoder, OpenMptModuleExt};
+use crate::encoder::{AudioFormat, SAMPLE_RATE};
+use crate::misc::safe_filename;
+use crate::render::{render_chunk, RenderEvent, RenderSettings, MAX_RENDER_SECONDS};
+
+/// 100 ms
+const CHUNK_FRAMES: usize = SAMPLE_RATE as usize / 10;
+
+pub struct Stems {
+    pub num_stems: usize,
+    /// Channels which are never heard don't get a file
+    pub num_silent: usize,
+}
+
+/// Renders every channel of the subsong with the others muted and writes the files into a ZIP file at `path`.
+/// `progress` is called with the amount of channels done and the total after each channel.
+/// Blocking, run it off the async runtime.
+pub fn render_stems(data: &Arc<[u8]>, settings: &RenderSettings, subsong: i32, format: AudioFormat, path: &Path, progress: impl Fn(usize, usize)) -> Result<Stems> {
+    let module = OpenMptModuleExt::from_memory(data)
+        .ok_or(anyhow!("Only tracker modules have channels"))?;
+    let num_channels = module.num_channels().max(0) as usize;
+    let channel_names = module.module().channel_names();
+    if num_channels == 0 {
+        return Err(anyhow!("This module has no channels"));
+    }
+    if module.module().duration_seconds() > MAX_RENDER_SECONDS {
+        return Err(anyhow!("Modules longer than {} minutes can't be rendered", MAX_RENDER_SECONDS as u32 / 60));
+    }
+    drop(module);
+
+    // FLAC and Opus are compressed already
+    let compression = match format {
+        AudioFormat::Wav => CompressionMethod::Deflated,
+        AudioFormat::Flac | AudioFormat::Opus => CompressionMethod::Stored,
+    };
+    let options = SimpleFileOptions::default()
+        .compression_method(compression)
+        .large_file(true);
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let mut stems = Stems { num_stems: 0, num_silent: 0 };
+
+    for channel in 0..num_channels {
+        // A fresh instance for every channel, so that nothing carries over from the previous one
+        let mut module = OpenMptModuleExt::from_memory(data)
+            .ok_or(anyhow!("Failed to initialize libopenmpt module"))?;
+        settings.apply(module.module());
+        module.module_mut().select_subsong(subsong);
+        for other in 0..num_channels {
+            module.set_channel_mute(other as i32, other != channel);
+        }
+
+        let mut encoder = format.encoder()?;
+        let mut buffer = vec![0f32; CHUNK_FRAMES * 2];
+        let mut frames_rendered = 0;
+        let mut audible = false;
+        loop {
+            let (frames_read, event) = render_chunk(module.module_mut(), false, SAMPLE_RATE as i32, &mut buffer);
+            let samples = &buffer[..frames_read * 2];
+            audible |= samples.iter().any(|sample| sample.abs() > f32::EPSILON);
+            encoder.write(samples)?;
+            frames_rendered += frames_read;
+
+            // Some modules don't know how long they are
+            if event == RenderEvent::Ended || frames_rendered as f64 / SAMPLE_RATE as f64 > MAX_RENDER_SECONDS {
+                break;
+            }
+        }
+
+        if audible {
+            let name = channel_names.get(channel).map(|name| safe_filename(name)).unwrap_or_default();
+            let filename = if name.is_empty() {
+                format!("{:02}.{}", channel + 1, format.extension())
+            } else {
+                format!("{:02}_{name}.{}", channel + 1, format.extension())
+            };
+            zip.start_file(filename, options)?;
+            zip.write_all(&encoder.finish()?)?;
+            stems.num_stems += 1;
+        } else {
+            stems.num_silent += 1;
+        }
+        progress(channel + 1, num_channels);
+    }
+
+    if stems.num_stems == 0 {
+        return Err(anyhow!("Every channel of this module is silent"));
+    }
+    zip.finish()?;
+
+    Ok(stems)
+}
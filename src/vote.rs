@@ -23,7 +23,7 @@ use libopenmpt_sys::openmpt_module_get_metadata;
 use serenity::all::{ChannelId, ComponentInteraction, Context, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, GuildId, Mentionable, UserId};
 use tokio::sync::{mpsc::Sender, RwLock};
 
-use crate::{botdata::BotDataKey, session::{VoiceSession, VoiceSessionData, VoiceSessionHandle, VoiceSessionNotificationMessage}};
+use crate::{botdata::BotDataKey, session::{preload_next_in_queue, VoiceSession, VoiceSessionData, VoiceSessionHandle, VoiceSessionNotificationMessage}};
 
 #[derive(Debug, Clone)]
 pub enum VoteKind {
@@ -177,6 +177,7 @@ pub async fn end_vote(ctx: Context, session: &VoiceSessionHandle) {
                     let mut session_lock = session.data.write().await;
                     session_lock.module_queue.remove(index);
                     drop(session_lock);
+                    preload_next_in_queue(&session.data).await;
                 },
             }
         };
@@ -0,0 +1,251 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Song overview images: a waveform on top of a spectrogram, with order boundaries marked.
+
+use std::{collections::VecDeque, f32::consts::PI, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use libopenmpt_sys::openmpt_module_get_current_order;
+
+use crate::decoder;
+use crate::nowplaying::EMBED_COLOUR;
+use crate::render::{render_chunk, RenderEvent, RenderSettings, MAX_RENDER_SECONDS};
+
+const WIDTH: usize = 1200;
+const WAVEFORM_HEIGHT: usize = 160;
+const SPECTROGRAM_HEIGHT: usize = 160;
+const HEIGHT: usize = WAVEFORM_HEIGHT + SPECTROGRAM_HEIGHT;
+
+/// Plenty for a picture, and halves the work compared to playback
+const SAMPLE_RATE: i32 = 24000;
+const FFT_SIZE: usize = 1024;
+const LOWEST_FREQUENCY: f32 = 40.0;
+const HIGHEST_FREQUENCY: f32 = 12000.0;
+/// Spectrogram levels below this are drawn as background
+const FLOOR_DB: f32 = -90.0;
+
+/// Discord's dark theme background
+const BACKGROUND: [u8; 3] = [0x2B, 0x2D, 0x31];
+const ORDER_LINE: [u8; 3] = [0x4E, 0x50, 0x58];
+
+pub struct Waveform {
+    pub png: Vec<u8>,
+    /// Length of the song covered by a single column
+    pub seconds_per_column: f64,
+}
+
+/// Renders the selected subsong of a module into a PNG image. Blocking, run it off the async runtime.
+pub fn waveform(data: &Arc<[u8]>, settings: &RenderSettings, subsong: i32) -> Result<Waveform> {
+    let mut module = decoder::open(data)?;
+    if let Some(openmpt) = module.openmpt() {
+        settings.apply(openmpt);
+    }
+    module.select_subsong(subsong);
+
+    let duration = module.duration_seconds();
+    if duration <= 0.0 {
+        return Err(anyhow!("The length of this module is unknown"));
+    }
+    if duration > MAX_RENDER_SECONDS {
+        return Err(anyhow!("Modules longer than {} minutes can't be drawn", MAX_RENDER_SECONDS as u32 / 60));
+    }
+    let frames_per_column = ((duration * SAMPLE_RATE as f64 / WIDTH as f64).ceil() as usize).max(1);
+
+    let mut columns: Vec<Column> = Vec::with_capacity(WIDTH);
+    let mut order_boundaries = Vec::new();
+    let mut last_order = None;
+    let mut column_samples: Vec<f32> = Vec::with_capacity(frames_per_column);
+    // Columns of short modules are narrower than the FFT window, so the spectrum is taken from the most recent samples
+    let mut history: VecDeque<f32> = VecDeque::from(vec![0.0; FFT_SIZE]);
+    // Small chunks, so that order changes are placed accurately
+    let mut buffer = vec![0f32; frames_per_column.min(512) * 2];
+    while columns.len() < WIDTH {
+        if let Some(openmpt) = module.openmpt() {
+            let order = unsafe { openmpt_module_get_current_order(openmpt.0) };
+            if last_order.is_some_and(|last_order| last_order != order) {
+                order_boundaries.push(columns.len());
+            }
+            last_order = Some(order);
+        }
+
+        let wanted = (frames_per_column - column_samples.len()).min(buffer.len() / 2);
+        let (frames_read, event) = render_chunk(module.as_mut(), false, SAMPLE_RATE, &mut buffer[..wanted * 2]);
+        for frame in buffer[..frames_read * 2].chunks_exact(2) {
+            let sample = (frame[0] + frame[1]) / 2.0;
+            column_samples.push(sample);
+            history.pop_front();
+            history.push_back(sample);
+        }
+
+        if column_samples.len() == frames_per_column || event == RenderEvent::Ended {
+            columns.push(Column::analyze(&column_samples, &history));
+            column_samples.clear();
+        }
+        if event == RenderEvent::Ended {
+            break;
+        }
+    }
+
+    Ok(Waveform {
+        png: draw(&columns, &order_boundaries)?,
+        seconds_per_column: frames_per_column as f64 / SAMPLE_RATE as f64,
+    })
+}
+
+struct Column {
+    min: f32,
+    max: f32,
+    /// Level in dB for each spectrogram row, from the bottom
+    spectrum: Vec<f32>,
+}
+
+impl Column {
+    /// `history` holds the last FFT_SIZE samples, a single window per column is enough for an overview
+    fn analyze(samples: &[f32], history: &VecDeque<f32>) -> Self {
+        let min = samples.iter().copied().fold(0.0, f32::min);
+        let max = samples.iter().copied().fold(0.0, f32::max);
+
+        let mut real: Vec<f32> = history.iter().enumerate()
+            .map(|(i, sample)| sample * (0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
+            .collect();
+        let mut imaginary = vec![0f32; FFT_SIZE];
+        fft(&mut real, &mut imaginary);
+
+        let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        let spectrum = (0..SPECTROGRAM_HEIGHT)
+            .map(|row| {
+                // Logarithmic frequency scale, each row covers a range of bins
+                let frequency = |row: f32| LOWEST_FREQUENCY * (HIGHEST_FREQUENCY / LOWEST_FREQUENCY).powf(row / SPECTROGRAM_HEIGHT as f32);
+                let first = (frequency(row as f32) / bin_width) as usize;
+                let last = ((frequency(row as f32 + 1.0) / bin_width) as usize).max(first + 1);
+                let power = (first..last.min(FFT_SIZE / 2))
+                    .map(|bin| real[bin].powi(2) + imaginary[bin].powi(2))
+                    .fold(0.0, f32::max);
+                // Normalized so that a full scale sine is around 0 dB
+                (10.0 * (power * 16.0 / (FFT_SIZE * FFT_SIZE) as f32).log10()).max(FLOOR_DB)
+            })
+            .collect();
+
+        Self { min, max, spectrum }
+    }
+}
+
+/// In-place radix-2 FFT, the length must be a power of two
+fn fft(real: &mut [f32], imaginary: &mut [f32]) {
+    let n = real.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imaginary.swap(i, j);
+        }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_real = real[b] * cos - imaginary[b] * sin;
+                let t_imaginary = real[b] * sin + imaginary[b] * cos;
+                real[b] = real[a] - t_real;
+                imaginary[b] = imaginary[a] - t_imaginary;
+                real[a] += t_real;
+                imaginary[a] += t_imaginary;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+/// Black through the embed colour to white
+fn spectrogram_colour(level_db: f32) -> [u8; 3] {
+    let accent = EMBED_COLOUR.to_be_bytes();
+    let t = ((level_db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+    let mix = |from: u8, to: u8, t: f32| (from as f32 + (to as f32 - from as f32) * t) as u8;
+
+    std::array::from_fn(|channel| {
+        if t < 0.6 {
+            mix(BACKGROUND[channel], accent[channel + 1], t / 0.6)
+        } else {
+            mix(accent[channel + 1], 0xFF, (t - 0.6) / 0.4)
+        }
+    })
+}
+
+fn draw(columns: &[Column], order_boundaries: &[usize]) -> Result<Vec<u8>> {
+    let accent = EMBED_COLOUR.to_be_bytes();
+    let mut pixels = vec![0u8; WIDTH * HEIGHT * 3];
+    let mut put = |x: usize, y: usize, colour: [u8; 3]| {
+        let offset = (y * WIDTH + x) * 3;
+        pixels[offset..offset + 3].copy_from_slice(&colour);
+    };
+
+    for x in 0..WIDTH {
+        for y in 0..WAVEFORM_HEIGHT {
+            put(x, y, BACKGROUND);
+        }
+        let Some(column) = columns.get(x) else {
+            for y in WAVEFORM_HEIGHT..HEIGHT {
+                put(x, y, BACKGROUND);
+            }
+            continue;
+        };
+
+        if order_boundaries.contains(&x) {
+            for y in 0..WAVEFORM_HEIGHT {
+                put(x, y, ORDER_LINE);
+            }
+        }
+        let centre = WAVEFORM_HEIGHT as f32 / 2.0;
+        let top = (centre - column.max.clamp(-1.0, 1.0) * centre) as usize;
+        let bottom = (centre - column.min.clamp(-1.0, 1.0) * centre) as usize;
+        for y in top..=bottom.min(WAVEFORM_HEIGHT - 1) {
+            put(x, y, [accent[1], accent[2], accent[3]]);
+        }
+
+        for (row, level) in column.spectrum.iter().enumerate() {
+            let colour = if order_boundaries.contains(&x) && row % 4 == 0 {
+                ORDER_LINE
+            } else {
+                spectrogram_colour(*level)
+            };
+            put(x, HEIGHT - 1 - row, colour);
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+
+    Ok(png)
+}
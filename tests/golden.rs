@@ -0,0 +1,296 @@
+/*
+ * This file is part of Modulo.
+ *
+ * Copyright (C) 2024-present Polyzium
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+// Golden audio tests for the render path. Every case renders a module from tests/data through
+// RenderSettings::apply and render_chunk, the same code voice sessions play through, and compares
+// a fingerprint of the output against tests/golden/<case>.txt.
+//
+// The fingerprint holds the level and the high frequency level of each channel per 100 ms block,
+// along with subsong switches and the end of the song. It's tolerant enough to survive floating point
+// differences between machines, but catches changes in interpolation, filtering, timing or mixing.
+//
+// After an intended change in sound (e.g. a libopenmpt upgrade), regenerate the references with
+// MODULO_BLESS=1 cargo test --test golden
+// and check the diff of tests/golden before committing it.
+
+use std::{env, fmt::Write, fs, path::PathBuf, sync::Arc};
+
+use modulo::{decoder::{self, Decoder}, render::{render_chunk, RenderEvent, RenderSettings}, session::Interpolation};
+
+const SAMPLE_RATE: i32 = 48000;
+/// 100 ms
+const BLOCK_FRAMES: usize = SAMPLE_RATE as usize / 10;
+/// Nothing bundled is this long, stops runaway renders if end of song detection breaks
+const MAX_BLOCKS: usize = 600;
+/// Levels are compared in dB
+const TOLERANCE_DB: f64 = 0.5;
+const SILENCE_DB: f64 = -90.0;
+
+struct Render {
+    fingerprint: String,
+    events: Vec<(usize, RenderEvent)>,
+}
+
+fn load(file: &str) -> Box<dyn Decoder> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data").join(file);
+    let bytes: Arc<[u8]> = fs::read(&path).unwrap().into();
+
+    decoder::open(&bytes).unwrap()
+}
+
+fn level_db(sum_of_squares: f64, count: usize) -> f64 {
+    let rms = (sum_of_squares / count.max(1) as f64).sqrt();
+    if rms <= 0.0 {
+        return SILENCE_DB;
+    }
+
+    (20.0 * rms.log10()).max(SILENCE_DB)
+}
+
+fn render(file: &str, settings: &RenderSettings) -> Render {
+    let mut module = load(file);
+    if let Some(openmpt) = module.openmpt() {
+        settings.apply(openmpt);
+    }
+
+    let mut fingerprint = String::new();
+    let mut events = Vec::new();
+    let mut buffer = vec![0f32; BLOCK_FRAMES * 2];
+    let mut frames_rendered = 0;
+    for _ in 0..MAX_BLOCKS {
+        let (frames_read, event) = render_chunk(module.as_mut(), settings.autosubsong, SAMPLE_RATE, &mut buffer);
+
+        let [left, right] = [0, 1].map(|channel| {
+            let samples: Vec<f64> = buffer[..frames_read * 2].iter().skip(channel).step_by(2).map(|s| *s as f64).collect();
+            let level = samples.iter().map(|s| s * s).sum();
+            // First difference works as a crude high pass filter
+            let high_level = samples.windows(2).map(|pair| (pair[1] - pair[0]).powi(2)).sum();
+
+            (level_db(level, frames_read), level_db(high_level, frames_read))
+        });
+        writeln!(fingerprint, "{:.1} {:.1} {:.1} {:.1}", left.0, right.0, left.1, right.1).unwrap();
+
+        frames_rendered += frames_read;
+        match event {
+            RenderEvent::Playing => continue,
+            RenderEvent::NextSubsong(subsong) => {
+                writeln!(fingerprint, "subsong {subsong} at {frames_rendered}").unwrap();
+                events.push((frames_rendered, event));
+            },
+            RenderEvent::Ended => {
+                writeln!(fingerprint, "end at {frames_rendered}").unwrap();
+                events.push((frames_rendered, event));
+                break;
+            },
+        }
+    }
+
+    Render { fingerprint, events }
+}
+
+/// Compares the fingerprint against the reference, or overwrites the reference when blessing.
+fn check(case: &str, render: &Render) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{case}.txt"));
+    if env::var_os("MODULO_BLESS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &render.fingerprint).unwrap();
+        return;
+    }
+
+    let reference = fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("No reference for {case}, run the tests with MODULO_BLESS=1 to create it"));
+    let expected: Vec<&str> = reference.lines().collect();
+    let actual: Vec<&str> = render.fingerprint.lines().collect();
+    assert_eq!(expected.len(), actual.len(), "{case}: length differs from the reference");
+
+    for (line, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        let parse = |s: &str| s.split(' ').map(|v| v.parse::<f64>()).collect::<Result<Vec<_>, _>>();
+        match (parse(expected), parse(actual)) {
+            (Ok(expected_values), Ok(actual_values)) => {
+                let matches = expected_values.len() == actual_values.len()
+                    && expected_values.iter().zip(actual_values.iter()).all(|(e, a)| (e - a).abs() <= TOLERANCE_DB);
+                assert!(matches, "{case}: block {line} differs from the reference\nexpected: {expected}\nactual:   {actual}");
+            },
+            // Events have to match exactly
+            _ => assert_eq!(expected, actual, "{case}: line {line} differs from the reference"),
+        }
+    }
+}
+
+fn settings() -> RenderSettings {
+    RenderSettings::default()
+}
+
+#[test]
+fn interpolation_default() {
+    check("interpolation_default", &render("subsongs.mod", &settings()));
+}
+
+#[test]
+fn interpolation_none() {
+    let settings = RenderSettings { interpolation: Interpolation::None, ..settings() };
+    check("interpolation_none", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn interpolation_linear() {
+    let settings = RenderSettings { interpolation: Interpolation::Linear, ..settings() };
+    check("interpolation_linear", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn interpolation_cubic() {
+    let settings = RenderSettings { interpolation: Interpolation::Cubic, ..settings() };
+    check("interpolation_cubic", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn interpolation_sinc8() {
+    let settings = RenderSettings { interpolation: Interpolation::Sinc8, ..settings() };
+    check("interpolation_sinc8", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn amiga_a500() {
+    let settings = RenderSettings { amiga_enabled: true, amiga_mode: "a500".to_owned(), ..settings() };
+    check("amiga_a500", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn amiga_a1200() {
+    let settings = RenderSettings { amiga_enabled: true, amiga_mode: "a1200".to_owned(), ..settings() };
+    check("amiga_a1200", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn amiga_unfiltered() {
+    let settings = RenderSettings { amiga_enabled: true, amiga_mode: "unfiltered".to_owned(), ..settings() };
+    check("amiga_unfiltered", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn stereo_separation_mono() {
+    let settings = RenderSettings { stereo_separation: 0, ..settings() };
+    let render = render("subsongs.mod", &settings);
+    for line in render.fingerprint.lines().filter(|line| !line.contains(char::is_alphabetic)) {
+        let values: Vec<f64> = line.split(' ').map(|value| value.parse().unwrap()).collect();
+        assert!((values[0] - values[1]).abs() <= TOLERANCE_DB, "Channels differ with no stereo separation: {line}");
+        assert!((values[2] - values[3]).abs() <= TOLERANCE_DB, "Channels differ with no stereo separation: {line}");
+    }
+    check("stereo_separation_mono", &render);
+}
+
+#[test]
+fn tempo_factor() {
+    let settings = RenderSettings { tempo_factor: 2.0, ..settings() };
+    check("tempo_factor", &render("subsongs.mod", &settings));
+}
+
+#[test]
+fn subsong_end_without_autosubsong() {
+    let render = render("subsongs.mod", &settings());
+    assert!(matches!(render.events.as_slice(), [(_, RenderEvent::Ended)]));
+    check("subsong_end_without_autosubsong", &render);
+}
+
+#[test]
+fn subsong_switching_with_autosubsong() {
+    let settings = RenderSettings { autosubsong: true, ..settings() };
+    let render = render("subsongs.mod", &settings);
+    assert!(matches!(render.events.as_slice(), [(_, RenderEvent::NextSubsong(1)), (_, RenderEvent::Ended)]));
+    check("subsong_switching_with_autosubsong", &render);
+}
+
+#[test]
+fn ym() {
+    check("ym", &render("chip.ym", &settings()));
+}
+
+#[test]
+fn audio_file() {
+    check("audio_file", &render("sweep.wav", &settings()));
+}
+
+/// Plays silence of the given lengths as subsongs
+struct FakeDecoder {
+    subsongs: Vec<usize>,
+    selected: i32,
+    position: usize,
+}
+
+impl Decoder for FakeDecoder {
+    fn read(&mut self, _sample_rate: i32, buffer: &mut [f32]) -> usize {
+        let frames = (buffer.len()/2).min(self.subsongs[self.selected as usize] - self.position);
+        buffer[..frames*2].fill(0.0);
+        self.position += frames;
+
+        frames
+    }
+
+    fn metadata(&self, _key: &str) -> String {
+        String::new()
+    }
+
+    fn metadata_keys(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn duration_seconds(&self) -> f64 {
+        0.0
+    }
+
+    fn position_seconds(&self) -> f64 {
+        0.0
+    }
+
+    fn num_subsongs(&self) -> i32 {
+        self.subsongs.len() as i32
+    }
+
+    fn selected_subsong(&self) -> i32 {
+        self.selected
+    }
+
+    fn select_subsong(&mut self, subsong: i32) -> bool {
+        if subsong as usize >= self.subsongs.len() {
+            return false;
+        }
+        self.selected = subsong;
+        self.position = 0;
+
+        true
+    }
+}
+
+#[test]
+fn render_chunk_reports_events() {
+    let mut module = FakeDecoder { subsongs: vec![150, 100], selected: 0, position: 0 };
+    let mut buffer = [0f32; 200];
+
+    assert_eq!(render_chunk(&mut module, true, SAMPLE_RATE, &mut buffer), (100, RenderEvent::Playing));
+    assert_eq!(render_chunk(&mut module, true, SAMPLE_RATE, &mut buffer), (50, RenderEvent::NextSubsong(1)));
+    // A subsong which fills the buffer exactly only ends on the next read
+    assert_eq!(render_chunk(&mut module, true, SAMPLE_RATE, &mut buffer), (100, RenderEvent::Playing));
+    assert_eq!(render_chunk(&mut module, true, SAMPLE_RATE, &mut buffer), (0, RenderEvent::Ended));
+
+    let mut module = FakeDecoder { subsongs: vec![150, 100], selected: 0, position: 0 };
+    render_chunk(&mut module, false, SAMPLE_RATE, &mut buffer);
+    assert_eq!(render_chunk(&mut module, false, SAMPLE_RATE, &mut buffer), (50, RenderEvent::Ended));
+    assert_eq!(module.selected_subsong(), 0);
+}